@@ -0,0 +1,148 @@
+// binary encodings of the C-instruction fields, keyed by their mnemonic
+
+// a-bit followed by the six c-bits
+pub const COMP_TABLE: [(&str, u16); 28] = [
+    ("0", 0b0101010),
+    ("1", 0b0111111),
+    ("-1", 0b0111010),
+    ("D", 0b0001100),
+    ("A", 0b0110000),
+    ("!D", 0b0001101),
+    ("!A", 0b0110001),
+    ("-D", 0b0001111),
+    ("-A", 0b0110011),
+    ("D+1", 0b0011111),
+    ("A+1", 0b0110111),
+    ("D-1", 0b0001110),
+    ("A-1", 0b0110010),
+    ("D+A", 0b0000010),
+    ("D-A", 0b0010011),
+    ("A-D", 0b0000111),
+    ("D&A", 0b0000000),
+    ("D|A", 0b0010101),
+    ("M", 0b1110000),
+    ("!M", 0b1110001),
+    ("-M", 0b1110011),
+    ("M+1", 0b1110111),
+    ("M-1", 0b1110010),
+    ("D+M", 0b1000010),
+    ("D-M", 0b1010011),
+    ("M-D", 0b1000111),
+    ("D&M", 0b1000000),
+    ("D|M", 0b1010101),
+];
+
+pub const DEST_TABLE: [(&str, u16); 8] = [
+    ("", 0b000),
+    ("M", 0b001),
+    ("D", 0b010),
+    ("MD", 0b011),
+    ("A", 0b100),
+    ("AM", 0b101),
+    ("AD", 0b110),
+    ("AMD", 0b111),
+];
+
+pub const JUMP_TABLE: [(&str, u16); 8] = [
+    ("", 0b000),
+    ("JGT", 0b001),
+    ("JEQ", 0b010),
+    ("JGE", 0b011),
+    ("JLT", 0b100),
+    ("JNE", 0b101),
+    ("JLE", 0b110),
+    ("JMP", 0b111),
+];
+
+fn lookup(table: &[(&str, u16)], mnemonic: &str) -> Option<u16> {
+    table
+        .iter()
+        .find(|(key, _)| *key == mnemonic)
+        .map(|(_, bits)| *bits)
+}
+
+pub fn comp(mnemonic: &str) -> Option<u16> {
+    lookup(&COMP_TABLE, mnemonic)
+}
+
+pub fn dest(mnemonic: &str) -> Option<u16> {
+    lookup(&DEST_TABLE, mnemonic)
+}
+
+pub fn jump(mnemonic: &str) -> Option<u16> {
+    lookup(&JUMP_TABLE, mnemonic)
+}
+
+pub fn mnemonics(table: &'static [(&'static str, u16)]) -> impl Iterator<Item = &'static str> {
+    table.iter().map(|(key, _)| *key)
+}
+
+// 111a cccc ccdd djjj
+pub fn encode_c(comp: u16, dest: u16, jump: u16) -> u16 {
+    0b111 << 13 | comp << 6 | dest << 3 | jump
+}
+
+fn reverse_lookup(table: &[(&'static str, u16)], bits: u16) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(_, value)| *value == bits)
+        .map(|(key, _)| *key)
+}
+
+pub fn comp_mnemonic(bits: u16) -> Option<&'static str> {
+    reverse_lookup(&COMP_TABLE, bits)
+}
+
+pub fn dest_mnemonic(bits: u16) -> Option<&'static str> {
+    reverse_lookup(&DEST_TABLE, bits)
+}
+
+pub fn jump_mnemonic(bits: u16) -> Option<&'static str> {
+    reverse_lookup(&JUMP_TABLE, bits)
+}
+
+fn without_whitespace(field: &str) -> String {
+    field.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+fn table_key(table: &'static [(&'static str, u16)], mnemonic: &str) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(key, _)| *key == mnemonic)
+        .map(|(key, _)| *key)
+}
+
+// table spelling of a comp, accepting whitespace and swapped operands of + & |
+pub fn canonical_comp(comp: &str) -> Option<&'static str> {
+    let comp = without_whitespace(comp);
+    if let Some(key) = table_key(&COMP_TABLE, &comp) {
+        return Some(key);
+    }
+    ['+', '&', '|'].into_iter().find_map(|op| {
+        let (lhs, rhs) = comp.split_once(op)?;
+        table_key(&COMP_TABLE, &format!("{rhs}{op}{lhs}"))
+    })
+}
+
+// table spelling of a dest, accepting A, M and D in any order
+pub fn canonical_dest(dest: &str) -> Option<&'static str> {
+    let dest = without_whitespace(dest);
+    let mut bits = 0;
+    for register in dest.chars() {
+        let bit = match register {
+            'A' => 0b100,
+            'D' => 0b010,
+            'M' => 0b001,
+            _ => return None,
+        };
+        if bits & bit != 0 {
+            return None;
+        }
+        bits |= bit;
+    }
+    dest_mnemonic(bits)
+}
+
+pub fn canonical_jump(jump: &str) -> Option<&'static str> {
+    table_key(&JUMP_TABLE, &without_whitespace(jump))
+}
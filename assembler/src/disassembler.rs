@@ -0,0 +1,120 @@
+use crate::code;
+use crate::error::{AsmError, ErrorKind, Span};
+use std::collections::BTreeSet;
+
+// read a .hack file, one 16 character binary word per line
+pub fn parse_hack(source: &str) -> Result<Vec<u16>, Vec<AsmError>> {
+    let mut words = vec![];
+    let mut errors = vec![];
+
+    for (line_idx, raw_line) in source.lines().enumerate() {
+        let line = raw_line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let is_binary = line.len() == 16 && line.chars().all(|c| c == '0' || c == '1');
+        match u16::from_str_radix(line, 2) {
+            Ok(word) if is_binary => words.push(word),
+            _ => {
+                let indent = raw_line.len() - raw_line.trim_start().len();
+                let span = Span::new(line_idx + 1, indent + 1, line.len());
+                errors.push(AsmError::new(
+                    ErrorKind::InvalidBinary(line.to_string()),
+                    span,
+                ));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(words)
+    } else {
+        Err(errors)
+    }
+}
+
+fn is_jump(word: u16) -> bool {
+    word & 0x8000 != 0 && word & 0b111 != 0
+}
+
+fn label_name(address: u16) -> String {
+    format!("L{address}")
+}
+
+fn decode_c(word: u16, address: usize) -> Result<String, AsmError> {
+    // the span refers to the word's line in a .hack file without blank lines
+    let span = Span::new(address + 1, 1, 16);
+    if word & 0xE000 != 0xE000 {
+        return Err(AsmError::new(ErrorKind::InvalidCPrefix(word), span));
+    }
+
+    let comp = code::comp_mnemonic(word >> 6 & 0b111_1111)
+        .ok_or_else(|| AsmError::new(ErrorKind::UndefinedComp(word), span))?;
+    let dest = code::dest_mnemonic(word >> 3 & 0b111).unwrap();
+    let jump = code::jump_mnemonic(word & 0b111).unwrap();
+
+    let mut asm = String::new();
+    if !dest.is_empty() {
+        asm += &format!("{dest}=");
+    }
+    asm += comp;
+    if !jump.is_empty() {
+        asm += &format!(";{jump}");
+    }
+    Ok(asm)
+}
+
+// assembly of a single word, None for C-instructions with undefined bits
+pub fn disassemble_word(word: u16) -> Option<String> {
+    if word & 0x8000 == 0 {
+        Some(format!("@{word}"))
+    } else {
+        decode_c(word, 0).ok()
+    }
+}
+
+// turn machine code back into assembly, naming every jump target with a synthetic label
+pub fn disassemble(words: &[u16]) -> Result<String, Vec<AsmError>> {
+    // an A-instruction directly followed by a jump holds the jump target
+    let jump_targets: BTreeSet<u16> = words
+        .windows(2)
+        .filter(|pair| pair[0] & 0x8000 == 0 && is_jump(pair[1]))
+        .map(|pair| pair[0])
+        .filter(|target| (*target as usize) <= words.len())
+        .collect();
+
+    let mut lines = vec![];
+    let mut errors = vec![];
+    for (address, word) in words.iter().enumerate() {
+        if jump_targets.contains(&(address as u16)) {
+            lines.push(format!("({})", label_name(address as u16)));
+        }
+
+        if word & 0x8000 == 0 {
+            let jumps_next = words.get(address + 1).is_some_and(|next| is_jump(*next));
+            if jumps_next && jump_targets.contains(word) {
+                lines.push(format!("@{}", label_name(*word)));
+            } else {
+                lines.push(format!("@{word}"));
+            }
+            continue;
+        }
+
+        match decode_c(*word, address) {
+            Ok(asm) => lines.push(asm),
+            Err(error) => errors.push(error),
+        }
+    }
+
+    // a jump may target the address right after the last instruction
+    if jump_targets.contains(&(words.len() as u16)) {
+        lines.push(format!("({})", label_name(words.len() as u16)));
+    }
+
+    if errors.is_empty() {
+        Ok(lines.join("\n") + "\n")
+    } else {
+        Err(errors)
+    }
+}
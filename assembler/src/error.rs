@@ -0,0 +1,297 @@
+use std::fmt;
+
+// location of a problem in the source, 1-based line and column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+    // index into the SourceMap, 0 for the file being assembled
+    pub file: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize, len: usize) -> Self {
+        Span {
+            line,
+            column,
+            len,
+            file: 0,
+        }
+    }
+
+    pub fn in_file(self, file: usize) -> Self {
+        Span { file, ..self }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownComp(String),
+    UnknownDest(String),
+    UnknownJump(String),
+    MissingComp,
+    EmptySymbol,
+    InvalidSymbol(String),
+    InvalidLabel(String),
+    InvalidBinary(String),
+    UndefinedComp(u16),
+    InvalidCPrefix(u16),
+    ConstantOutOfRange(String),
+    DuplicateLabel {
+        name: String,
+        first_line: usize,
+    },
+    RedefinedBuiltin(String),
+    VariableOverflow(String),
+    ProgramTooLarge(usize),
+    InvalidMacroName(String),
+    DuplicateMacro(String),
+    NestedMacro,
+    UnexpectedEndm,
+    UnterminatedMacro(String),
+    UnknownMacroParam(String),
+    MacroArity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    MacroRecursion(String),
+    UnknownDirective(String),
+    InvalidDirective(String),
+    UndefinedExport(String),
+    ImportedLabelDefined(String),
+    UnresolvedImport(String),
+    DuplicateExport(String),
+    ExportUsedAsVariable(String),
+    InvalidObject(String),
+    InvalidExpression(String),
+    ExpressionOverflow(String),
+    ExpressionOutOfRange {
+        expr: String,
+        value: i64,
+    },
+    DivisionByZero(String),
+    UndefinedSymbol(String),
+    RelocatableExpression(String),
+    DuplicateConstant(String),
+    NonCanonical {
+        written: String,
+        canonical: String,
+    },
+    InvalidInclude(String),
+    IncludeNotFound(String),
+    IncludeCycle(String),
+    IncludeRead {
+        path: String,
+        reason: String,
+    },
+    IncludeInMacro,
+}
+
+impl ErrorKind {
+    pub fn is_warning(&self) -> bool {
+        matches!(self, ErrorKind::NonCanonical { .. })
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::UnknownComp(comp) => write!(f, "unknown comp `{comp}`"),
+            ErrorKind::UnknownDest(dest) => write!(f, "unknown dest `{dest}`"),
+            ErrorKind::UnknownJump(jump) => write!(f, "unknown jump `{jump}`"),
+            ErrorKind::MissingComp => write!(f, "missing comp in C-instruction"),
+            ErrorKind::EmptySymbol => write!(f, "expected a value or symbol after `@`"),
+            ErrorKind::InvalidSymbol(symbol) => write!(f, "invalid symbol `{symbol}`"),
+            ErrorKind::InvalidLabel(label) => write!(f, "invalid label declaration `{label}`"),
+            ErrorKind::InvalidBinary(line) => {
+                write!(f, "expected a 16-bit binary word, found `{line}`")
+            }
+            ErrorKind::UndefinedComp(word) => {
+                write!(f, "undefined comp bits in instruction `{word:016b}`")
+            }
+            ErrorKind::InvalidCPrefix(word) => {
+                write!(f, "C-instruction `{word:016b}` must start with `111`")
+            }
+            ErrorKind::ConstantOutOfRange(value) => {
+                write!(f, "constant `{value}` does not fit in 15 bits (max 32767)")
+            }
+            ErrorKind::DuplicateLabel { name, first_line } => {
+                write!(f, "label `{name}` is already defined on line {first_line}")
+            }
+            ErrorKind::RedefinedBuiltin(name) => {
+                write!(f, "`{name}` redefines a predefined symbol")
+            }
+            ErrorKind::VariableOverflow(name) => write!(
+                f,
+                "no RAM left for variable `{name}`, allocation would overlap SCREEN memory"
+            ),
+            ErrorKind::ProgramTooLarge(size) => {
+                write!(f, "program has {size} instructions, ROM only holds 32768")
+            }
+            ErrorKind::InvalidMacroName(name) => write!(f, "invalid macro name `{name}`"),
+            ErrorKind::DuplicateMacro(name) => write!(f, "macro `{name}` is already defined"),
+            ErrorKind::NestedMacro => write!(f, "macro definitions cannot be nested"),
+            ErrorKind::UnexpectedEndm => write!(f, "`.endm` without a matching `.macro`"),
+            ErrorKind::UnterminatedMacro(name) => {
+                write!(f, "macro `{name}` is missing its `.endm`")
+            }
+            ErrorKind::UnknownMacroParam(param) => {
+                write!(f, "unknown macro parameter `%{param}`")
+            }
+            ErrorKind::MacroArity {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "macro `{name}` takes {expected} argument(s) but {found} were given"
+            ),
+            ErrorKind::MacroRecursion(name) => {
+                write!(f, "expansion of macro `{name}` is too deeply nested")
+            }
+            ErrorKind::UnknownDirective(directive) => {
+                write!(f, "unknown directive `{directive}`")
+            }
+            ErrorKind::InvalidDirective(line) => write!(f, "invalid directive `{line}`"),
+            ErrorKind::UndefinedExport(name) => {
+                write!(f, "exported symbol `{name}` is not a label in this file")
+            }
+            ErrorKind::ImportedLabelDefined(name) => {
+                write!(f, "imported symbol `{name}` is also defined as a label")
+            }
+            ErrorKind::UnresolvedImport(name) => {
+                write!(f, "imported symbol `{name}` is not exported by any object")
+            }
+            ErrorKind::DuplicateExport(name) => {
+                write!(f, "symbol `{name}` is exported more than once")
+            }
+            ErrorKind::ExportUsedAsVariable(name) => write!(
+                f,
+                "`{name}` is exported by another object but used as a variable, add `.import {name}`"
+            ),
+            ErrorKind::InvalidObject(line) => write!(f, "malformed object file line `{line}`"),
+            ErrorKind::InvalidExpression(expr) => write!(f, "invalid expression `{expr}`"),
+            ErrorKind::ExpressionOverflow(expr) => write!(f, "expression `{expr}` overflows"),
+            ErrorKind::ExpressionOutOfRange { expr, value } => write!(
+                f,
+                "expression `{expr}` evaluates to {value}, which does not fit in 15 bits (0..=32767)"
+            ),
+            ErrorKind::DivisionByZero(expr) => write!(f, "division by zero in `{expr}`"),
+            ErrorKind::UndefinedSymbol(name) => {
+                write!(f, "`{name}` is not a label, constant or predefined symbol")
+            }
+            ErrorKind::RelocatableExpression(name) => write!(
+                f,
+                "label `{name}` cannot be used in an expression when assembling an object file"
+            ),
+            ErrorKind::DuplicateConstant(name) => {
+                write!(f, "constant `{name}` is already defined")
+            }
+            ErrorKind::NonCanonical { written, canonical } => {
+                write!(f, "`{written}` is not canonical, write `{canonical}`")
+            }
+            ErrorKind::InvalidInclude(line) => {
+                write!(f, "invalid include `{line}`, expected `.include \"file.asm\"`")
+            }
+            ErrorKind::IncludeNotFound(name) => {
+                write!(f, "included file `{name}` not found in the include path")
+            }
+            ErrorKind::IncludeCycle(chain) => write!(f, "include cycle: {chain}"),
+            ErrorKind::IncludeRead { path, reason } => {
+                write!(f, "could not read included file `{path}`: {reason}")
+            }
+            ErrorKind::IncludeInMacro => write!(f, "`.include` is not allowed inside a macro"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub kind: ErrorKind,
+    pub span: Span,
+    pub suggestion: Option<String>,
+}
+
+impl AsmError {
+    pub fn new(kind: ErrorKind, span: Span) -> Self {
+        AsmError {
+            kind,
+            span,
+            suggestion: None,
+        }
+    }
+
+    pub fn with_suggestion(mut self, suggestion: Option<String>) -> Self {
+        self.suggestion = suggestion;
+        self
+    }
+
+    // rustc-style diagnostic with the offending source line underlined
+    pub fn render(&self, file_name: &str, source: &str) -> String {
+        let Span {
+            line, column, len, ..
+        } = self.span;
+        // tabs are shown as single spaces so the caret lines up with the column
+        let source_line = source
+            .lines()
+            .nth(line.saturating_sub(1))
+            .unwrap_or("")
+            .replace('\t', " ");
+        let gutter = " ".repeat(line.to_string().len());
+
+        let severity = if self.kind.is_warning() {
+            "warning"
+        } else {
+            "error"
+        };
+        let mut out = format!("{severity}: {}\n", self.kind);
+        out += &format!("{gutter}--> {file_name}:{line}:{column}\n");
+        out += &format!("{gutter} |\n");
+        out += &format!("{line} | {source_line}\n");
+        out += &format!(
+            "{gutter} | {}{}\n",
+            " ".repeat(column.saturating_sub(1)),
+            "^".repeat(len.max(1))
+        );
+        if let Some(suggestion) = &self.suggestion {
+            out += &format!("{gutter} = help: did you mean `{suggestion}`?\n");
+        }
+        out
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.column, self.kind)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+// closest candidate within a couple of edits, used for "did you mean" hints
+pub fn suggest<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let input = input.to_uppercase();
+    candidates
+        .into_iter()
+        .filter(|candidate| !candidate.is_empty())
+        .map(|candidate| (edit_distance(&input, candidate), candidate))
+        .filter(|(distance, candidate)| *distance <= 2 && *distance < candidate.len().max(2))
+        .min_by_key(|(distance, candidate)| (*distance, *candidate))
+        .map(|(_, candidate)| candidate.to_string())
+}
@@ -0,0 +1,213 @@
+use crate::error::ErrorKind;
+use crate::parser::is_valid_symbol;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    And,
+    Or,
+}
+
+impl BinOp {
+    fn from_char(c: char) -> Option<BinOp> {
+        match c {
+            '+' => Some(BinOp::Add),
+            '-' => Some(BinOp::Sub),
+            '*' => Some(BinOp::Mul),
+            '/' => Some(BinOp::Div),
+            '%' => Some(BinOp::Rem),
+            '&' => Some(BinOp::And),
+            '|' => Some(BinOp::Or),
+            _ => None,
+        }
+    }
+
+    fn symbol(&self) -> char {
+        match self {
+            BinOp::Add => '+',
+            BinOp::Sub => '-',
+            BinOp::Mul => '*',
+            BinOp::Div => '/',
+            BinOp::Rem => '%',
+            BinOp::And => '&',
+            BinOp::Or => '|',
+        }
+    }
+
+    // C-like binding strength, higher binds tighter
+    fn precedence(&self) -> u8 {
+        match self {
+            BinOp::Or => 1,
+            BinOp::And => 2,
+            BinOp::Add | BinOp::Sub => 3,
+            BinOp::Mul | BinOp::Div | BinOp::Rem => 4,
+        }
+    }
+}
+
+// constant expression in an A-instruction or .equ directive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(i64),
+    Symbol(String),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, BinOp, Box<Expr>),
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Number(value) => write!(f, "{value}"),
+            Expr::Symbol(name) => write!(f, "{name}"),
+            Expr::Neg(expr) => match **expr {
+                Expr::Binary(..) => write!(f, "-({expr})"),
+                _ => write!(f, "-{expr}"),
+            },
+            Expr::Binary(lhs, op, rhs) => {
+                let needs_parens = |expr: &Expr, right: bool| match expr {
+                    Expr::Binary(_, inner, _) => {
+                        inner.precedence() < op.precedence()
+                            || (right && inner.precedence() == op.precedence())
+                    }
+                    _ => false,
+                };
+                if needs_parens(lhs, false) {
+                    write!(f, "({lhs})")?;
+                } else {
+                    write!(f, "{lhs}")?;
+                }
+                write!(f, "{}", op.symbol())?;
+                if needs_parens(rhs, true) {
+                    write!(f, "({rhs})")
+                } else {
+                    write!(f, "{rhs}")
+                }
+            }
+        }
+    }
+}
+
+// decimal, 0x hexadecimal or 0b binary literal
+pub fn parse_number(text: &str) -> Option<i64> {
+    let (digits, radix) = if let Some(hex) = text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+        (hex, 16)
+    } else if let Some(bin) = text.strip_prefix("0b").or(text.strip_prefix("0B")) {
+        (bin, 2)
+    } else {
+        (text, 10)
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    i64::from_str_radix(digits, radix).ok()
+}
+
+struct ExprParser<'a> {
+    tokens: Vec<&'a str>,
+    idx: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn tokenize(text: &'a str) -> Option<Vec<&'a str>> {
+        let mut tokens = vec![];
+        let mut rest = text.trim_start();
+        while let Some(c) = rest.chars().next() {
+            let len = if BinOp::from_char(c).is_some() || c == '(' || c == ')' {
+                1
+            } else if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$' | ':') {
+                rest.find(|c: char| {
+                    !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$' | ':'))
+                })
+                .unwrap_or(rest.len())
+            } else {
+                return None;
+            };
+            tokens.push(&rest[..len]);
+            rest = rest[len..].trim_start();
+        }
+        Some(tokens)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.idx).copied()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.peek();
+        self.idx += 1;
+        token
+    }
+
+    fn primary(&mut self) -> Option<Expr> {
+        match self.next()? {
+            "-" => Some(Expr::Neg(Box::new(self.primary()?))),
+            "(" => {
+                let expr = self.expression(0)?;
+                (self.next()? == ")").then_some(expr)
+            }
+            token => parse_number(token)
+                .map(Expr::Number)
+                .or_else(|| is_valid_symbol(token).then(|| Expr::Symbol(token.to_string()))),
+        }
+    }
+
+    // precedence climbing over the binary operators
+    fn expression(&mut self, min_precedence: u8) -> Option<Expr> {
+        let mut lhs = self.primary()?;
+        while let Some(op) = self
+            .peek()
+            .and_then(|token| token.chars().next())
+            .and_then(BinOp::from_char)
+        {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.next();
+            let rhs = self.expression(op.precedence() + 1)?;
+            lhs = Expr::Binary(Box::new(lhs), op, Box::new(rhs));
+        }
+        Some(lhs)
+    }
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Option<Expr> {
+        let mut parser = ExprParser {
+            tokens: ExprParser::tokenize(text)?,
+            idx: 0,
+        };
+        let expr = parser.expression(0)?;
+        (parser.idx == parser.tokens.len()).then_some(expr)
+    }
+
+    // evaluate with `lookup` resolving symbol names
+    pub fn eval(&self, lookup: &impl Fn(&str) -> Result<i64, ErrorKind>) -> Result<i64, ErrorKind> {
+        let overflow = || ErrorKind::ExpressionOverflow(self.to_string());
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Symbol(name) => lookup(name),
+            Expr::Neg(expr) => expr.eval(lookup)?.checked_neg().ok_or_else(overflow),
+            Expr::Binary(lhs, op, rhs) => {
+                let (lhs, rhs) = (lhs.eval(lookup)?, rhs.eval(lookup)?);
+                if matches!(op, BinOp::Div | BinOp::Rem) && rhs == 0 {
+                    return Err(ErrorKind::DivisionByZero(self.to_string()));
+                }
+                let value = match op {
+                    BinOp::Add => lhs.checked_add(rhs),
+                    BinOp::Sub => lhs.checked_sub(rhs),
+                    BinOp::Mul => lhs.checked_mul(rhs),
+                    BinOp::Div => lhs.checked_div(rhs),
+                    BinOp::Rem => lhs.checked_rem(rhs),
+                    BinOp::And => Some(lhs & rhs),
+                    BinOp::Or => Some(lhs | rhs),
+                };
+                value.ok_or_else(overflow)
+            }
+        }
+    }
+}
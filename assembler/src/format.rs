@@ -0,0 +1,99 @@
+use crate::parser::{parse_lines, strip_comment, Instruction};
+use crate::preprocessor::SourceLine;
+
+// indentation of instructions, labels and directives start at column 0
+const INDENT: &str = "    ";
+
+// a source line split into its formatted code and trailing comment
+struct Line {
+    code: String,
+    comment: Option<String>,
+}
+
+// canonical text of a line of code, or the line itself when it is not a plain
+// instruction (macro invocations and bodies, lines with errors)
+fn format_code(code: &str) -> String {
+    let source_line = SourceLine {
+        text: code.to_string(),
+        line: 1,
+        file: 0,
+        origin: None,
+    };
+    match parse_lines(&[source_line]).as_deref() {
+        Ok([Instruction::C(c)]) => format!("{INDENT}{c}"),
+        Ok([Instruction::A(_)]) => format!("{INDENT}@{}", code[1..].trim()),
+        Ok([Instruction::Label(label)]) => format!("({})", label.name),
+        _ if code.starts_with('.') => {
+            let words: Vec<&str> = code.split_whitespace().collect();
+            words.join(" ")
+        }
+        _ if code.starts_with('(') => code.to_string(),
+        _ => format!("{INDENT}{code}"),
+    }
+}
+
+fn split_line(raw_line: &str) -> Line {
+    let code = strip_comment(raw_line);
+    let comment = raw_line[code.len()..].trim_end();
+    let code = code.trim();
+    let comment = (!comment.is_empty()).then(|| comment.to_string());
+    if code.is_empty() {
+        // full-line comments keep their indentation if they had any
+        let indent = if raw_line.starts_with(char::is_whitespace) {
+            INDENT
+        } else {
+            ""
+        };
+        return Line {
+            code: indent.to_string(),
+            comment,
+        };
+    }
+    Line {
+        code: format_code(code),
+        comment,
+    }
+}
+
+// trailing comments of consecutive lines are aligned one column past the longest code
+fn align_block(block: &[Line], out: &mut String) {
+    let column = block
+        .iter()
+        .filter(|line| line.comment.is_some() && !line.code.trim().is_empty())
+        .map(|line| line.code.len() + 1)
+        .max()
+        .unwrap_or(0);
+    for line in block {
+        match &line.comment {
+            Some(comment) if !line.code.trim().is_empty() => {
+                *out += &format!("{:<column$}{comment}\n", line.code);
+            }
+            Some(comment) => *out += &format!("{}{comment}\n", line.code),
+            None => *out += &format!("{}\n", line.code),
+        }
+    }
+}
+
+// canonical layout of an assembly source: instructions indented under labels,
+// canonical C-instruction spelling, trailing comments aligned within each block
+// and runs of blank lines collapsed to one
+pub fn format(source: &str) -> String {
+    let mut out = String::new();
+    let mut block = vec![];
+    let mut blank = false;
+    for raw_line in source.lines() {
+        if raw_line.trim().is_empty() {
+            align_block(&block, &mut out);
+            block.clear();
+            blank = true;
+            continue;
+        }
+        if blank && !out.is_empty() {
+            out.push('\n');
+        }
+        blank = false;
+        block.push(split_line(raw_line));
+    }
+    align_block(&block, &mut out);
+    out
+}
@@ -0,0 +1,217 @@
+use parser::MAX_CONSTANT;
+use std::collections::{HashMap, HashSet};
+
+pub mod code;
+pub mod disassembler;
+pub mod error;
+pub mod expr;
+pub mod format;
+pub mod linker;
+pub mod listing;
+pub mod object;
+pub mod optimize;
+pub mod output;
+pub mod parser;
+pub mod preprocessor;
+pub mod source;
+pub mod symbol_table;
+
+pub use error::{AsmError, ErrorKind, Span};
+pub use expr::Expr;
+pub use parser::{
+    parse, AInstruction, CInstruction, Directive, DirectiveKind, Instruction, Label, Value,
+};
+pub use symbol_table::{SymbolKind, SymbolTable};
+
+// machine code together with the symbols it was resolved against
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub words: Vec<u16>,
+    pub symbols: SymbolTable,
+}
+
+// number of words in the instruction ROM
+pub const ROM_SIZE: usize = 32768;
+
+// first pass, shared by programs and object files: ROM addresses of every label
+pub(crate) fn define_labels(
+    instructions: &[Instruction],
+    relocatable: bool,
+) -> (SymbolTable, Vec<AsmError>) {
+    let mut symbols = SymbolTable::new();
+    let mut errors = vec![];
+
+    let mut label_lines: HashMap<&str, usize> = HashMap::new();
+    let mut address = 0;
+    for instruction in instructions {
+        if instruction.is_code() {
+            address += 1;
+            if address == ROM_SIZE + 1 {
+                let size = instructions.iter().filter(|i| i.is_code()).count();
+                let kind = ErrorKind::ProgramTooLarge(size);
+                errors.push(AsmError::new(kind, instruction.span()));
+            }
+            continue;
+        }
+        let Instruction::Label(label) = instruction else {
+            continue;
+        };
+
+        match symbols.add_label(&label.name, address as u16) {
+            Ok(()) => {
+                label_lines.insert(&label.name, label.span.line);
+            }
+            Err(existing) if existing.kind == SymbolKind::Predefined => {
+                let kind = ErrorKind::RedefinedBuiltin(label.name.clone());
+                errors.push(AsmError::new(kind, label.span));
+            }
+            Err(_) => {
+                let kind = ErrorKind::DuplicateLabel {
+                    name: label.name.clone(),
+                    first_line: label_lines[label.name.as_str()],
+                };
+                errors.push(AsmError::new(kind, label.span));
+            }
+        }
+    }
+
+    // then .equ constants, in order so each can use the ones before it
+    for instruction in instructions {
+        let Instruction::Directive(Directive {
+            kind: DirectiveKind::Equ(name, expr),
+            span,
+        }) = instruction
+        else {
+            continue;
+        };
+
+        let value = match eval_operand(expr, &symbols, relocatable) {
+            Ok(value) => value,
+            Err(kind) => {
+                errors.push(AsmError::new(kind, *span));
+                continue;
+            }
+        };
+        match symbols.add_constant(name, value) {
+            Ok(()) => {}
+            Err(existing) if existing.kind == SymbolKind::Predefined => {
+                let kind = ErrorKind::RedefinedBuiltin(name.clone());
+                errors.push(AsmError::new(kind, *span));
+            }
+            Err(_) => {
+                let kind = ErrorKind::DuplicateConstant(name.clone());
+                errors.push(AsmError::new(kind, *span));
+            }
+        }
+    }
+
+    (symbols, errors)
+}
+
+// evaluate an A-instruction operand, labels are only usable once their address is final
+pub(crate) fn eval_operand(
+    expr: &Expr,
+    symbols: &SymbolTable,
+    relocatable: bool,
+) -> Result<u16, ErrorKind> {
+    let value = expr.eval(&|name| match symbols.symbol(name) {
+        Some(symbol) if symbol.kind == SymbolKind::Label && relocatable => {
+            Err(ErrorKind::RelocatableExpression(name.to_string()))
+        }
+        Some(symbol) if symbol.kind != SymbolKind::Variable => Ok(symbol.address as i64),
+        _ => Err(ErrorKind::UndefinedSymbol(name.to_string())),
+    })?;
+
+    if (0..=MAX_CONSTANT as i64).contains(&value) {
+        Ok(value as u16)
+    } else {
+        Err(ErrorKind::ExpressionOutOfRange {
+            expr: expr.to_string(),
+            value,
+        })
+    }
+}
+
+// check .export/.import against the labels, returning the imported names
+pub(crate) fn check_directives<'a>(
+    instructions: &'a [Instruction],
+    symbols: &SymbolTable,
+    errors: &mut Vec<AsmError>,
+) -> HashSet<&'a str> {
+    let mut imports = HashSet::new();
+    for instruction in instructions {
+        let Instruction::Directive(directive) = instruction else {
+            continue;
+        };
+        let is_label =
+            |name| symbols.symbol(name).map(|symbol| symbol.kind) == Some(SymbolKind::Label);
+        match &directive.kind {
+            DirectiveKind::Export(name) if !is_label(name) => {
+                let kind = ErrorKind::UndefinedExport(name.clone());
+                errors.push(AsmError::new(kind, directive.span));
+            }
+            DirectiveKind::Import(name) if is_label(name) => {
+                let kind = ErrorKind::ImportedLabelDefined(name.clone());
+                errors.push(AsmError::new(kind, directive.span));
+            }
+            DirectiveKind::Import(name) => {
+                imports.insert(name.as_str());
+            }
+            DirectiveKind::Export(_) | DirectiveKind::Equ(..) => {}
+        }
+    }
+    imports
+}
+
+// resolve labels and variables of parsed instructions into machine code
+pub fn assemble_instructions(instructions: &[Instruction]) -> Result<Program, Vec<AsmError>> {
+    let (mut symbols, mut errors) = define_labels(instructions, false);
+    let imports = check_directives(instructions, &symbols, &mut errors);
+
+    // translate instructions to binary
+    let mut words = vec![];
+    for instruction in instructions {
+        match instruction {
+            Instruction::A(a) => {
+                let value = match &a.value {
+                    Value::Constant(constant) => *constant,
+                    Value::Expression(expr) => match eval_operand(expr, &symbols, false) {
+                        Ok(value) => value,
+                        Err(kind) => {
+                            errors.push(AsmError::new(kind, a.span));
+                            continue;
+                        }
+                    },
+                    // imports only resolve when linking object files
+                    Value::Symbol(symbol) if imports.contains(symbol.as_str()) => {
+                        let kind = ErrorKind::UnresolvedImport(symbol.clone());
+                        errors.push(AsmError::new(kind, a.span));
+                        continue;
+                    }
+                    Value::Symbol(symbol) => match symbols.get_or_allocate(symbol) {
+                        Some(address) => address,
+                        None => {
+                            let kind = ErrorKind::VariableOverflow(symbol.clone());
+                            errors.push(AsmError::new(kind, a.span));
+                            continue;
+                        }
+                    },
+                };
+                words.push(value);
+            }
+            Instruction::C(c) => words.push(c.encode()),
+            Instruction::Label(_) | Instruction::Directive(_) => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(Program { words, symbols })
+    } else {
+        Err(errors)
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Vec<u16>, Vec<AsmError>> {
+    let instructions = parse(source)?;
+    Ok(assemble_instructions(&instructions)?.words)
+}
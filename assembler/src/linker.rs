@@ -0,0 +1,103 @@
+use crate::error::ErrorKind;
+use crate::object::{ObjectFile, Relocation};
+use crate::parser::MAX_CONSTANT;
+use crate::symbol_table::{SymbolKind, SymbolTable};
+use crate::{Program, ROM_SIZE};
+use std::fmt;
+
+// a problem found while linking, attributed to the object it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkError {
+    pub object: String,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.object, self.kind)
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+// lay objects out in ROM in order, resolve exported labels across them and
+// allocate variables globally from RAM 16 upward
+pub fn link(objects: &[ObjectFile]) -> Result<Program, Vec<LinkError>> {
+    let mut symbols = SymbolTable::new();
+    let mut errors = vec![];
+    let error = |object: &ObjectFile, kind| LinkError {
+        object: object.name.clone(),
+        kind,
+    };
+
+    // base ROM address of every object
+    let mut bases = vec![];
+    let mut size = 0;
+    for object in objects {
+        bases.push(size as u16);
+        size += object.words.len();
+    }
+    if size > ROM_SIZE {
+        let last = objects.last().unwrap();
+        return Err(vec![error(last, ErrorKind::ProgramTooLarge(size))]);
+    }
+
+    for (object, base) in objects.iter().zip(&bases) {
+        for (name, address) in &object.exports {
+            let Some(address) = base.checked_add(*address) else {
+                let kind = ErrorKind::InvalidObject(format!("export {name} {address}"));
+                errors.push(error(object, kind));
+                continue;
+            };
+            match symbols.add_label(name, address) {
+                Ok(()) => {}
+                Err(existing) if existing.kind == SymbolKind::Predefined => {
+                    errors.push(error(object, ErrorKind::RedefinedBuiltin(name.clone())));
+                }
+                Err(_) => errors.push(error(object, ErrorKind::DuplicateExport(name.clone()))),
+            }
+        }
+    }
+
+    let mut words: Vec<u16> = Vec::with_capacity(size);
+    for (object, base) in objects.iter().zip(&bases) {
+        let start = words.len();
+        words.extend(&object.words);
+
+        for (address, relocation) in &object.relocations {
+            let word = &mut words[start + *address as usize];
+            match relocation {
+                Relocation::Rom => match word.checked_add(*base) {
+                    // the relocated address must still fit an A-instruction
+                    Some(relocated) if relocated <= MAX_CONSTANT => *word = relocated,
+                    _ => {
+                        let kind = ErrorKind::InvalidObject(format!("{:016b} rom", *word));
+                        errors.push(error(object, kind));
+                    }
+                },
+                Relocation::Import(name) => match symbols.symbol(name) {
+                    Some(symbol) if symbol.kind == SymbolKind::Label => *word = symbol.address,
+                    _ => errors.push(error(object, ErrorKind::UnresolvedImport(name.clone()))),
+                },
+                Relocation::Variable(name) => match symbols.symbol(name) {
+                    Some(symbol) if symbol.kind == SymbolKind::Label => {
+                        let kind = ErrorKind::ExportUsedAsVariable(name.clone());
+                        errors.push(error(object, kind));
+                    }
+                    _ => match symbols.get_or_allocate(name) {
+                        Some(address) => *word = address,
+                        None => {
+                            errors.push(error(object, ErrorKind::VariableOverflow(name.clone())))
+                        }
+                    },
+                },
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(Program { words, symbols })
+    } else {
+        Err(errors)
+    }
+}
@@ -0,0 +1,39 @@
+use crate::parser::Instruction;
+use crate::source::SourceMap;
+use crate::symbol_table::{SymbolKind, SymbolTable};
+
+// ROM address, binary and hex word next to the source line each instruction came from
+pub fn listing(sources: &SourceMap, instructions: &[Instruction], words: &[u16]) -> String {
+    let mut out = format!("{:<6}{:<18}{:<6}SOURCE\n", "ROM", "BINARY", "HEX");
+
+    let mut address = 0;
+    for instruction in instructions {
+        let source_line = sources.line(instruction.span()).trim_end();
+        if instruction.is_code() {
+            let word = words[address];
+            out += &format!("{address:<6}{word:016b}  {word:04X}  {source_line}\n");
+            address += 1;
+        } else {
+            out += &format!("{address:<6}{:<18}{:<6}{source_line}\n", "", "");
+        }
+    }
+    out
+}
+
+// labels with their ROM addresses, variables with their allocated RAM addresses
+// and the values of .equ constants
+pub fn symbol_file(symbols: &SymbolTable) -> String {
+    let mut out = String::from("// labels (ROM)\n");
+    for (name, address) in symbols.of_kind(SymbolKind::Label) {
+        out += &format!("{address:<6}{name}\n");
+    }
+    out += "// variables (RAM)\n";
+    for (name, address) in symbols.of_kind(SymbolKind::Variable) {
+        out += &format!("{address:<6}{name}\n");
+    }
+    out += "// constants (.equ)\n";
+    for (name, value) in symbols.of_kind(SymbolKind::Constant) {
+        out += &format!("{value:<6}{name}\n");
+    }
+    out
+}
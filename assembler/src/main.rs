@@ -1,159 +1,341 @@
-use std::collections::HashMap;
+use assembler::object::ObjectFile;
+use assembler::output::Format;
+use assembler::source::SourceMap;
+use assembler::AsmError;
 use std::fs::File;
-use std::io::Write;
-use std::io::{self, BufRead};
-use std::path::Path;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 
-fn main() -> io::Result<()> {
-    let comp_map: HashMap<&str, &str> = [
-        ("0", "0101010"),
-        ("1", "0111111"),
-        ("-1", "0111010"),
-        ("D", "0001100"),
-        ("A", "0110000"),
-        ("!D", "0001101"),
-        ("!A", "0110001"),
-        ("-D", "0001111"),
-        ("-A", "0110011"),
-        ("D+1", "0011111"),
-        ("A+1", "0110111"),
-        ("D-1", "0001110"),
-        ("A-1", "0110010"),
-        ("D+A", "0000010"),
-        ("D-A", "0010011"),
-        ("A-D", "0000111"),
-        ("D&A", "0000000"),
-        ("D|A", "0010101"),
-        ("M", "1110000"),
-        ("!M", "1110001"),
-        ("-M", "1110011"),
-        ("M+1", "1110111"),
-        ("M-1", "1110010"),
-        ("D+M", "1000010"),
-        ("D-M", "1010011"),
-        ("M-D", "1000111"),
-        ("D&M", "1000000"),
-        ("D|M", "1010101"),
-    ]
-    .into_iter()
-    .collect();
-
-    let dest_map: HashMap<&str, &str> = [
-        ("", "000"),
-        ("M", "001"),
-        ("D", "010"),
-        ("MD", "011"),
-        ("A", "100"),
-        ("AM", "101"),
-        ("AD", "110"),
-        ("AMD", "111"),
-    ]
-    .into_iter()
-    .collect();
-
-    let jump_map: HashMap<&str, &str> = [
-        ("", "000"),
-        ("JGT", "001"),
-        ("JEQ", "010"),
-        ("JGE", "011"),
-        ("JLT", "100"),
-        ("JNE", "101"),
-        ("JLE", "110"),
-        ("JMP", "111"),
-    ]
-    .into_iter()
-    .collect();
-
-    let mut symbol_table: HashMap<String, u16> = [
-        ("SP", 0),
-        ("LCL", 1),
-        ("ARG", 2),
-        ("THIS", 3),
-        ("THAT", 4),
-        ("R0", 0),
-        ("R1", 1),
-        ("R2", 2),
-        ("R3", 3),
-        ("R4", 4),
-        ("R5", 5),
-        ("R6", 6),
-        ("R7", 7),
-        ("R8", 8),
-        ("R9", 9),
-        ("R10", 10),
-        ("R11", 11),
-        ("R12", 12),
-        ("R13", 13),
-        ("R14", 14),
-        ("R15", 15),
-        ("SCREEN", 16384),
-        ("KBD", 24576),
-    ]
-    .into_iter()
-    .map(|(key, value)| (key.to_string(), value))
-    .collect();
-
-    // input file
-    let file_name = std::env::args().nth(1).expect("No arguments provided.");
-    let input_file = File::open(&file_name)?;
-    let reader = io::BufReader::new(input_file);
+// file name standing for stdin as input and stdout as output
+const STDIO: &str = "-";
+
+fn report_errors(sources: &SourceMap, errors: &[AsmError]) {
+    for error in errors {
+        eprintln!("{}", sources.render(error));
+    }
+    eprintln!(
+        "error: could not process `{}` due to {} previous error(s)",
+        sources.files[0].name,
+        errors.len()
+    );
+}
+
+#[derive(Default)]
+struct Options {
+    listing: bool,
+    symbols: bool,
+    // emit a relocatable object file instead of machine code
+    object: bool,
+    // warn about C-instructions not written in canonical spelling
+    strict: bool,
+    format: Format,
+    output: Option<String>,
+    // several inputs, so `output` names a directory
+    multiple: bool,
+    // run the peephole optimizer before emitting code
+    optimize: bool,
+    // report unformatted files instead of rewriting them
+    check: bool,
+    // searched for `.include`d files after the including file's directory
+    include_dirs: Vec<PathBuf>,
+}
+
+impl Options {
+    // where the output for an input file goes, None means stdout
+    fn output_path(&self, file_name: &str, extension: &str) -> Option<PathBuf> {
+        let stem = stem(file_name);
+        match self.output.as_deref() {
+            Some(STDIO) => None,
+            Some(dir) if self.multiple => Some(Path::new(dir).join(format!("{stem}.{extension}"))),
+            Some(path) => Some(PathBuf::from(path)),
+            None if file_name == STDIO => None,
+            None => Some(Path::new(file_name).with_extension(extension)),
+        }
+    }
+}
+
+fn stem(file_name: &str) -> &str {
+    if file_name == STDIO {
+        return "stdin";
+    }
+    Path::new(file_name)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("out")
+}
+
+// the contents of an input file, or None after reporting why it could not be read
+fn read_input(file_name: &str) -> Option<String> {
+    let source = if file_name == STDIO {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).map(|_| source)
+    } else {
+        std::fs::read_to_string(file_name)
+    };
+    source
+        .map_err(|e| eprintln!("error: could not read `{file_name}`: {e}"))
+        .ok()
+}
+
+fn write_output(path: Option<&Path>, content: impl AsRef<[u8]>) -> io::Result<()> {
+    match path {
+        Some(path) => File::create(path)?.write_all(content.as_ref()),
+        None => io::stdout().write_all(content.as_ref()),
+    }
+}
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {program} [--listing] [--symbols] [--strict] [-O] [-I <dir>]... [--format <format>] [-o <path>] <file.asm|->...\n       {program} --object [-O] [-I <dir>]... [-o <path>] <file.asm|->...\n       {program} link [--symbols] [--format <format>] [-o <path>] <file.obj>...\n       {program} disassemble [-o <path>] <file.hack|->...\n       {program} fmt [--check] [-o <path>] <file.asm|->..."
+    );
+    std::process::exit(1);
+}
+
+// handles one input file, returning whether it succeeded
+type Process = fn(&str, &Options) -> io::Result<bool>;
+
+// returns whether the file assembled without errors
+fn assemble_file(file_name: &str, options: &Options) -> io::Result<bool> {
+    let Some(source) = read_input(file_name) else {
+        return Ok(false);
+    };
+    let path = (file_name != STDIO).then(|| PathBuf::from(file_name));
+    let mut sources = SourceMap::default();
+    sources.add(file_name, path, source, None);
+    let mut instructions = match assembler::parser::parse_with(&mut sources, &options.include_dirs)
+    {
+        Ok(instructions) => instructions,
+        Err(errors) => {
+            report_errors(&sources, &errors);
+            return Ok(false);
+        }
+    };
+    if options.strict {
+        for warning in assembler::parser::spelling_warnings(&instructions) {
+            eprintln!("{}", sources.render(&warning));
+        }
+    }
+    if options.optimize {
+        let (optimized, report) = assembler::optimize::optimize(&instructions);
+        eprintln!("{file_name}: {report}");
+        instructions = optimized;
+    }
+
+    if options.object {
+        let object = match assembler::object::assemble_object(stem(file_name), &instructions) {
+            Ok(object) => object,
+            Err(errors) => {
+                report_errors(&sources, &errors);
+                return Ok(false);
+            }
+        };
+        let output_path = options.output_path(file_name, "obj");
+        write_output(output_path.as_deref(), object.to_text())?;
+        return Ok(true);
+    }
+
+    let program = match assembler::assemble_instructions(&instructions) {
+        Ok(program) => program,
+        Err(errors) => {
+            report_errors(&sources, &errors);
+            return Ok(false);
+        }
+    };
 
     // output file
-    let stem = Path::new(&file_name).file_stem().unwrap().to_str().unwrap();
-    let output_file_name = format!("{stem}.hack");
-    let mut output_file = File::create(output_file_name).unwrap();
-
-    // populate symbol table with labels
-    let mut address = 0;
-    for (_, line) in reader.lines().enumerate() {
-        let line = line?;
-        let line = line.trim();
-        if line.is_empty() || line.starts_with("//") {
+    let output_path = options.output_path(file_name, options.format.extension());
+    let output = assembler::output::encode(&program.words, options.format, stem(file_name));
+    write_output(output_path.as_deref(), output)?;
+
+    // listing and symbol files sit next to the output, or in the working directory for stdout
+    let side_file = |extension| {
+        output_path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(stem(file_name)))
+            .with_extension(extension)
+    };
+    if options.listing {
+        let listing = assembler::listing::listing(&sources, &instructions, &program.words);
+        write_output(Some(&side_file("lst")), listing)?;
+    }
+    if options.symbols {
+        let symbols = assembler::listing::symbol_file(&program.symbols);
+        write_output(Some(&side_file("sym")), symbols)?;
+    }
+
+    Ok(true)
+}
+
+// link object files into a single program named after the first object
+fn link_files(file_names: &[&str], options: &Options) -> io::Result<bool> {
+    let mut objects = vec![];
+    let mut failed = false;
+    for file_name in file_names {
+        let Some(source) = read_input(file_name) else {
+            failed = true;
             continue;
+        };
+        match ObjectFile::from_text(stem(file_name), &source) {
+            Ok(object) => objects.push(object),
+            Err(errors) => {
+                report_errors(&SourceMap::from_source(file_name, &source), &errors);
+                failed = true;
+            }
         }
+    }
+    if failed {
+        return Ok(false);
+    }
 
-        if line.starts_with("(") {
-            let label = line.trim_matches(|c| c == '(' || c == ')').to_string();
-            symbol_table.insert(label, address);
-        } else {
-            address += 1;
+    let program = match assembler::linker::link(&objects) {
+        Ok(program) => program,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("error: {error}");
+            }
+            eprintln!(
+                "error: could not link due to {} previous error(s)",
+                errors.len()
+            );
+            return Ok(false);
         }
+    };
+
+    let first = file_names[0];
+    let output_path = options.output_path(first, options.format.extension());
+    let output = assembler::output::encode(&program.words, options.format, stem(first));
+    write_output(output_path.as_deref(), output)?;
+    if options.symbols {
+        let symbols = assembler::listing::symbol_file(&program.symbols);
+        let path = output_path
+            .unwrap_or_else(|| PathBuf::from(stem(first)))
+            .with_extension("sym");
+        write_output(Some(&path), symbols)?;
     }
+    Ok(true)
+}
 
-    let input_file = File::open(file_name)?;
-    let reader = io::BufReader::new(input_file);
+fn disassemble_file(file_name: &str, options: &Options) -> io::Result<bool> {
+    let Some(source) = read_input(file_name) else {
+        return Ok(false);
+    };
+    let asm = match assembler::disassembler::parse_hack(&source)
+        .and_then(|words| assembler::disassembler::disassemble(&words))
+    {
+        Ok(asm) => asm,
+        Err(errors) => {
+            report_errors(&SourceMap::from_source(file_name, &source), &errors);
+            return Ok(false);
+        }
+    };
 
-    // translate asm lines to binary instructions
-    let mut memory_idx = 15; // 15 since R0-15 are reserved
-    for (_, line) in reader.lines().enumerate() {
-        let line = line?;
-        let line = line.trim();
-        if line.is_empty() || line.starts_with("//") || line.starts_with("(") {
-            continue;
+    // disassembly goes to stdout unless asked otherwise, so it never clobbers a source file
+    let output_path = options
+        .output
+        .as_ref()
+        .and_then(|_| options.output_path(file_name, "asm"));
+    write_output(output_path.as_deref(), asm)?;
+    Ok(true)
+}
+
+// rewrite a file in canonical layout, or with --check only report whether it is
+fn format_file(file_name: &str, options: &Options) -> io::Result<bool> {
+    let Some(source) = read_input(file_name) else {
+        return Ok(false);
+    };
+    let formatted = assembler::format::format(&source);
+    if options.check {
+        if formatted != source {
+            eprintln!("`{file_name}` is not formatted");
+            return Ok(false);
         }
+        return Ok(true);
+    }
+
+    let output_path = match options.output.as_deref() {
+        None if file_name != STDIO => Some(PathBuf::from(file_name)),
+        _ => options.output_path(file_name, "asm"),
+    };
+    if output_path.is_none() || formatted != source {
+        write_output(output_path.as_deref(), formatted)?;
+    }
+    Ok(true)
+}
 
-        let output = if line.starts_with("@") {
-            // A instructions
-            let value = line[1..].to_string();
-            let instruction = value
-                .parse::<u16>()
-                .ok()
-                .or(symbol_table.get(&value).copied()) // Try to get from the symbol table
-                .unwrap_or_else(|| {
-                    // If not present, allocate it to a new memory address
-                    memory_idx += 1;
-                    symbol_table.insert(value, memory_idx);
-                    memory_idx
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let mut options = Options::default();
+    let mut positional = vec![];
+    let mut args_iter = args[1..].iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--listing" | "-l" => options.listing = true,
+            "--symbols" | "-s" => options.symbols = true,
+            "--object" | "-c" => options.object = true,
+            "--strict" => options.strict = true,
+            "--check" => options.check = true,
+            "--optimize" | "-O" => options.optimize = true,
+            "--format" | "-f" => {
+                let format = args_iter.next().unwrap_or_else(|| usage(&args[0]));
+                options.format = format.parse().unwrap_or_else(|e| {
+                    eprintln!("error: {e}");
+                    std::process::exit(1);
                 });
-            format!("0{:015b}", instruction)
-        } else {
-            // C instrucitons
-            let (dest, rest) = line.split_once('=').unwrap_or(("", &line));
-            let (comp, jump) = rest.split_once(';').unwrap_or((rest, ""));
-            format!("111{}{}{}", comp_map[comp], dest_map[dest], jump_map[jump])
-        };
+            }
+            "--output" | "-o" => {
+                let output = args_iter.next().unwrap_or_else(|| usage(&args[0]));
+                options.output = Some(output.clone());
+            }
+            "--include" | "-I" => {
+                let dir = args_iter.next().unwrap_or_else(|| usage(&args[0]));
+                options.include_dirs.push(PathBuf::from(dir));
+            }
+            _ => positional.push(arg.as_str()),
+        }
+    }
+
+    // linking turns all inputs into one output
+    if let ["link", files @ ..] = positional.as_slice() {
+        if files.is_empty() {
+            usage(&args[0]);
+        }
+        if !link_files(files, &options)? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let (process, files): (Process, &[&str]) = match positional.as_slice() {
+        ["disassemble", files @ ..] => (disassemble_file, files),
+        ["fmt", files @ ..] => (format_file, files),
+        files => (assemble_file, files),
+    };
+    if files.is_empty() {
+        usage(&args[0]);
+    }
+
+    options.multiple = files.len() > 1;
+    if let Some(output) = options.output.as_deref() {
+        if options.multiple && (output == STDIO || !Path::new(output).is_dir()) {
+            eprintln!("error: `-o` must name an existing directory when processing several files");
+            std::process::exit(1);
+        }
+    }
 
-        writeln!(output_file, "{output}")?;
+    // a file that cannot be read or written fails on its own, the rest still run
+    let mut failed = 0;
+    for file_name in files {
+        match process(file_name, &options) {
+            Ok(true) => {}
+            Ok(false) => failed += 1,
+            Err(e) => {
+                eprintln!("error: {file_name}: {e}");
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        std::process::exit(1);
     }
 
     Ok(())
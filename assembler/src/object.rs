@@ -0,0 +1,205 @@
+use crate::error::{AsmError, ErrorKind, Span};
+use crate::parser::{is_valid_symbol, DirectiveKind, Instruction, Value};
+use crate::symbol_table::SymbolKind;
+use crate::{check_directives, define_labels, eval_operand};
+
+// how the linker patches a word of an object file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Relocation {
+    // object-relative ROM address, shifted by the object's base address
+    Rom,
+    // label exported by another object
+    Import(String),
+    // variable allocated by the linker
+    Variable(String),
+}
+
+// relocatable machine code of a single assembly file
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ObjectFile {
+    pub name: String,
+    pub words: Vec<u16>,
+    pub relocations: Vec<(u16, Relocation)>,
+    // exported labels with their object-relative ROM address
+    pub exports: Vec<(String, u16)>,
+    pub imports: Vec<String>,
+}
+
+// assemble instructions without fixing ROM or RAM addresses
+pub fn assemble_object(
+    name: &str,
+    instructions: &[Instruction],
+) -> Result<ObjectFile, Vec<AsmError>> {
+    let (symbols, mut errors) = define_labels(instructions, true);
+    let imports = check_directives(instructions, &symbols, &mut errors);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut object = ObjectFile {
+        name: name.to_string(),
+        ..Default::default()
+    };
+    for instruction in instructions {
+        let address = object.words.len() as u16;
+        match instruction {
+            Instruction::A(a) => {
+                let symbol = match &a.value {
+                    Value::Constant(constant) => {
+                        object.words.push(*constant);
+                        continue;
+                    }
+                    Value::Expression(expr) => {
+                        match eval_operand(expr, &symbols, true) {
+                            Ok(value) => object.words.push(value),
+                            Err(kind) => errors.push(AsmError::new(kind, a.span)),
+                        }
+                        continue;
+                    }
+                    Value::Symbol(symbol) => symbol,
+                };
+
+                let (word, relocation) = match symbols.symbol(symbol) {
+                    Some(symbol) if symbol.kind != SymbolKind::Label => (symbol.address, None),
+                    Some(label) => (label.address, Some(Relocation::Rom)),
+                    None if imports.contains(symbol.as_str()) => {
+                        (0, Some(Relocation::Import(symbol.clone())))
+                    }
+                    None => (0, Some(Relocation::Variable(symbol.clone()))),
+                };
+                object.words.push(word);
+                if let Some(relocation) = relocation {
+                    object.relocations.push((address, relocation));
+                }
+            }
+            Instruction::C(c) => object.words.push(c.encode()),
+            Instruction::Directive(directive) => match &directive.kind {
+                DirectiveKind::Export(name) => {
+                    let address = symbols.get(name).unwrap();
+                    object.exports.push((name.clone(), address));
+                }
+                DirectiveKind::Import(name) => object.imports.push(name.clone()),
+                DirectiveKind::Equ(..) => {}
+            },
+            Instruction::Label(_) => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(object)
+    } else {
+        Err(errors)
+    }
+}
+
+impl ObjectFile {
+    // text form, one word per line followed by its relocation if any
+    pub fn to_text(&self) -> String {
+        let mut out = String::from("// hack object file\n");
+        for (name, address) in &self.exports {
+            out += &format!("export {name} {address}\n");
+        }
+        for name in &self.imports {
+            out += &format!("import {name}\n");
+        }
+
+        let mut relocations = self.relocations.iter().peekable();
+        for (address, word) in self.words.iter().enumerate() {
+            out += &format!("{word:016b}");
+            if let Some((_, relocation)) = relocations.next_if(|(at, _)| *at as usize == address) {
+                match relocation {
+                    Relocation::Rom => out += " rom",
+                    Relocation::Import(name) => out += &format!(" import {name}"),
+                    Relocation::Variable(name) => out += &format!(" var {name}"),
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn from_text(name: &str, source: &str) -> Result<ObjectFile, Vec<AsmError>> {
+        let mut object = ObjectFile {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let mut errors = vec![];
+        // exported addresses and `rom` words are checked against the word count
+        // once every word is read
+        let mut rom_addresses = vec![];
+
+        for (line_idx, raw_line) in source.lines().enumerate() {
+            let line = raw_line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+
+            let address = object.words.len() as u16;
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let word = (parts[0].len() == 16)
+                .then(|| u16::from_str_radix(parts[0], 2).ok())
+                .flatten();
+            let indent = raw_line.len() - raw_line.trim_start().len();
+            let span = Span::new(line_idx + 1, indent + 1, line.len());
+            let valid = match (parts.as_slice(), word) {
+                (["export", name, address], _) if is_valid_symbol(name) => address
+                    .parse()
+                    .map(|address| {
+                        object.exports.push((name.to_string(), address));
+                        rom_addresses.push((address, line, span));
+                    })
+                    .is_ok(),
+                (["import", name], _) if is_valid_symbol(name) => {
+                    object.imports.push(name.to_string());
+                    true
+                }
+                ([_], Some(word)) => {
+                    object.words.push(word);
+                    true
+                }
+                // only A-instructions hold addresses to relocate
+                ([_, ..], Some(word)) if word & 0x8000 != 0 => false,
+                ([_, "rom"], Some(word)) => {
+                    object.words.push(word);
+                    object.relocations.push((address, Relocation::Rom));
+                    rom_addresses.push((word, line, span));
+                    true
+                }
+                ([_, "import", name], Some(word)) if is_valid_symbol(name) => {
+                    object.words.push(word);
+                    let relocation = Relocation::Import(name.to_string());
+                    object.relocations.push((address, relocation));
+                    true
+                }
+                ([_, "var", name], Some(word)) if is_valid_symbol(name) => {
+                    object.words.push(word);
+                    let relocation = Relocation::Variable(name.to_string());
+                    object.relocations.push((address, relocation));
+                    true
+                }
+                _ => false,
+            };
+
+            if !valid {
+                errors.push(AsmError::new(
+                    ErrorKind::InvalidObject(line.to_string()),
+                    span,
+                ));
+            }
+        }
+
+        // an address may point one past the last word, at a label ending the object
+        for (address, line, span) in rom_addresses {
+            if address as usize > object.words.len() {
+                let kind = ErrorKind::InvalidObject(line.to_string());
+                errors.push(AsmError::new(kind, span));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(object)
+        } else {
+            Err(errors)
+        }
+    }
+}
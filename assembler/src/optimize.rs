@@ -0,0 +1,236 @@
+use crate::parser::{AInstruction, CInstruction, Instruction, Value};
+use std::fmt;
+
+// instructions removed by each rewrite
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Report {
+    pub before: usize,
+    pub after: usize,
+    // `@SP M=M+1` directly undone by `@SP M=M-1`, or the other way round
+    pub push_pop_pairs: usize,
+    // A-loads overwritten before A is used
+    pub dead_loads: usize,
+    // A-loads of the value A already holds
+    pub redundant_loads: usize,
+    // jumps to the label right after them
+    pub jumps_to_next: usize,
+    // code after an unconditional jump that no label leads to
+    pub unreachable: usize,
+}
+
+impl Report {
+    pub fn saved(&self) -> usize {
+        self.before - self.after
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "optimized {} -> {} instructions, {} saved ({} push/pop, {} dead loads, {} redundant loads, {} jumps to next, {} unreachable)",
+            self.before,
+            self.after,
+            self.saved(),
+            self.push_pop_pairs,
+            self.dead_loads,
+            self.redundant_loads,
+            self.jumps_to_next,
+            self.unreachable
+        )
+    }
+}
+
+fn as_a(instruction: Option<&Instruction>) -> Option<&AInstruction> {
+    match instruction {
+        Some(Instruction::A(a)) => Some(a),
+        _ => None,
+    }
+}
+
+fn as_c(instruction: Option<&Instruction>) -> Option<&CInstruction> {
+    match instruction {
+        Some(Instruction::C(c)) => Some(c),
+        _ => None,
+    }
+}
+
+fn is_symbol(a: &AInstruction, name: &str) -> bool {
+    matches!(&a.value, Value::Symbol(symbol) if symbol == name)
+}
+
+// `M=<comp>` with no jump
+fn is_m_update(c: &CInstruction, comp: &str) -> bool {
+    c.dest == "M" && c.comp == comp && c.jump.is_empty()
+}
+
+// whether the next instruction that executes after `idx` loads A, so the
+// value left in A at `idx` is never read
+fn a_overwritten_after(instructions: &[Instruction], idx: usize) -> bool {
+    match instructions.get(idx) {
+        Some(Instruction::A(_)) => true,
+        Some(Instruction::Directive(_)) => a_overwritten_after(instructions, idx + 1),
+        _ => false,
+    }
+}
+
+fn remove_push_pop_pairs(instructions: &[Instruction], report: &mut Report) -> Vec<Instruction> {
+    let mut out = vec![];
+    let mut idx = 0;
+    while idx < instructions.len() {
+        let window = &instructions[idx..];
+        let pair = match (
+            as_a(window.first()),
+            as_c(window.get(1)),
+            as_a(window.get(2)),
+            as_c(window.get(3)),
+        ) {
+            (Some(a1), Some(c1), Some(a2), Some(c2)) => {
+                is_symbol(a1, "SP")
+                    && is_symbol(a2, "SP")
+                    && ((is_m_update(c1, "M+1") && is_m_update(c2, "M-1"))
+                        || (is_m_update(c1, "M-1") && is_m_update(c2, "M+1")))
+            }
+            _ => false,
+        };
+        if pair && a_overwritten_after(instructions, idx + 4) {
+            report.push_pop_pairs += 4;
+            idx += 4;
+        } else {
+            out.push(instructions[idx].clone());
+            idx += 1;
+        }
+    }
+    out
+}
+
+// what A is known to hold at a point of the program
+#[derive(Clone, Copy, PartialEq)]
+enum Known<'a> {
+    Unknown,
+    // A = value
+    Value(&'a Value),
+    // A = RAM[value]
+    Deref(&'a Value),
+}
+
+// drop A-loads that are overwritten straight away or reload what A already holds;
+// writes through a pointer are assumed never to change the pointer itself
+fn remove_redundant_loads(instructions: &[Instruction], report: &mut Report) -> Vec<Instruction> {
+    let mut out = vec![];
+    let mut known = Known::Unknown;
+    let mut idx = 0;
+    while idx < instructions.len() {
+        let instruction = &instructions[idx];
+        match instruction {
+            Instruction::A(a) => {
+                let deref_next = as_c(instructions.get(idx + 1))
+                    .is_some_and(|c| c.dest == "A" && c.comp == "M" && c.jump.is_empty());
+                if known == Known::Value(&a.value) {
+                    report.redundant_loads += 1;
+                } else if deref_next && known == Known::Deref(&a.value) {
+                    report.redundant_loads += 2;
+                    idx += 1;
+                } else if a_overwritten_after(instructions, idx + 1) {
+                    report.dead_loads += 1;
+                } else {
+                    out.push(instruction.clone());
+                    known = Known::Value(&a.value);
+                }
+            }
+            Instruction::C(c) => {
+                if c.dest.contains('A') {
+                    known = match known {
+                        Known::Value(value) if c.comp == "M" || c.dest.contains('M') => {
+                            Known::Deref(value)
+                        }
+                        _ => Known::Unknown,
+                    };
+                }
+                out.push(instruction.clone());
+            }
+            Instruction::Label(_) => {
+                known = Known::Unknown;
+                out.push(instruction.clone());
+            }
+            Instruction::Directive(_) => out.push(instruction.clone()),
+        }
+        idx += 1;
+    }
+    out
+}
+
+fn remove_jumps_to_next(instructions: &[Instruction], report: &mut Report) -> Vec<Instruction> {
+    let mut out = vec![];
+    let mut idx = 0;
+    while idx < instructions.len() {
+        let target = as_a(instructions.get(idx));
+        let jump = as_c(instructions.get(idx + 1));
+        let labels = instructions[(idx + 2).min(instructions.len())..]
+            .iter()
+            .take_while(|instruction| matches!(instruction, Instruction::Label(_)));
+        let label_count = labels.clone().count();
+        let to_next = match (target, jump) {
+            (Some(target), Some(jump)) => {
+                jump.dest.is_empty()
+                    && !jump.jump.is_empty()
+                    && labels.clone().any(|label| match label {
+                        Instruction::Label(label) => is_symbol(target, &label.name),
+                        _ => false,
+                    })
+            }
+            _ => false,
+        };
+        let after_labels = idx + 2 + label_count;
+        if to_next
+            && (after_labels == instructions.len()
+                || a_overwritten_after(instructions, after_labels))
+        {
+            report.jumps_to_next += 2;
+            idx += 2;
+        } else {
+            out.push(instructions[idx].clone());
+            idx += 1;
+        }
+    }
+    out
+}
+
+fn remove_unreachable(instructions: &[Instruction], report: &mut Report) -> Vec<Instruction> {
+    let mut out = vec![];
+    let mut reachable = true;
+    for instruction in instructions {
+        match instruction {
+            Instruction::Label(_) => reachable = true,
+            Instruction::A(_) | Instruction::C(_) if !reachable => {
+                report.unreachable += 1;
+                continue;
+            }
+            Instruction::C(c) if c.jump == "JMP" => reachable = false,
+            _ => {}
+        }
+        out.push(instruction.clone());
+    }
+    out
+}
+
+// apply the peephole rewrites until none of them changes the program any more
+pub fn optimize(instructions: &[Instruction]) -> (Vec<Instruction>, Report) {
+    let mut report = Report {
+        before: instructions.iter().filter(|i| i.is_code()).count(),
+        ..Default::default()
+    };
+    let mut current = instructions.to_vec();
+    loop {
+        let len = current.len();
+        current = remove_unreachable(&current, &mut report);
+        current = remove_jumps_to_next(&current, &mut report);
+        current = remove_push_pop_pairs(&current, &mut report);
+        current = remove_redundant_loads(&current, &mut report);
+        if current.len() == len {
+            break;
+        }
+    }
+    report.after = current.iter().filter(|i| i.is_code()).count();
+    (current, report)
+}
@@ -0,0 +1,128 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    // one ASCII binary word per line, as read by the course's CPU emulator
+    #[default]
+    Hack,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+    IntelHex,
+    // Verilog memory files for $readmemb / $readmemh
+    ReadMemB,
+    ReadMemH,
+    RustArray,
+    CArray,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hack" => Ok(Format::Hack),
+            "bin-le" => Ok(Format::BinaryLittleEndian),
+            "bin-be" => Ok(Format::BinaryBigEndian),
+            "ihex" => Ok(Format::IntelHex),
+            "readmemb" => Ok(Format::ReadMemB),
+            "readmemh" => Ok(Format::ReadMemH),
+            "rust" => Ok(Format::RustArray),
+            "c" => Ok(Format::CArray),
+            _ => Err(format!(
+                "unknown format `{s}`, expected one of: hack, bin-le, bin-be, ihex, readmemb, readmemh, rust, c"
+            )),
+        }
+    }
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Hack => "hack",
+            Format::BinaryLittleEndian | Format::BinaryBigEndian => "bin",
+            Format::IntelHex => "hex",
+            Format::ReadMemB | Format::ReadMemH => "mem",
+            Format::RustArray => "rs",
+            Format::CArray => "h",
+        }
+    }
+}
+
+// identifier for array formats, derived from the program name
+fn identifier(name: &str) -> String {
+    let ident: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if ident.starts_with(|c: char| c.is_ascii_digit()) || ident.is_empty() {
+        format!("_{ident}")
+    } else {
+        ident
+    }
+}
+
+// byte addressed records of up to 16 bytes, each word stored big-endian
+fn intel_hex(words: &[u16]) -> String {
+    let mut out = String::new();
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+    for (idx, chunk) in bytes.chunks(16).enumerate() {
+        let address = (idx * 16) as u16;
+        let mut record = vec![chunk.len() as u8];
+        record.extend(address.to_be_bytes());
+        record.push(0x00); // data record
+        record.extend(chunk);
+
+        let checksum = record
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+            .wrapping_neg();
+        out.push(':');
+        for byte in record.iter().chain([&checksum]) {
+            write!(out, "{byte:02X}").unwrap();
+        }
+        out.push('\n');
+    }
+    out += ":00000001FF\n"; // end of file record
+    out
+}
+
+fn array_items(words: &[u16]) -> String {
+    words
+        .chunks(8)
+        .map(|chunk| {
+            let items: Vec<String> = chunk.iter().map(|word| format!("0x{word:04X}")).collect();
+            format!("    {},\n", items.join(", "))
+        })
+        .collect()
+}
+
+// serialise machine code, `name` is used for the array formats
+pub fn encode(words: &[u16], format: Format, name: &str) -> Vec<u8> {
+    let text = match format {
+        Format::Hack | Format::ReadMemB => {
+            words.iter().map(|word| format!("{word:016b}\n")).collect()
+        }
+        Format::ReadMemH => words.iter().map(|word| format!("{word:04x}\n")).collect(),
+        Format::BinaryLittleEndian => {
+            return words.iter().flat_map(|word| word.to_le_bytes()).collect()
+        }
+        Format::BinaryBigEndian => {
+            return words.iter().flat_map(|word| word.to_be_bytes()).collect()
+        }
+        Format::IntelHex => intel_hex(words),
+        Format::RustArray => format!(
+            "pub const {}: [u16; {}] = [\n{}];\n",
+            identifier(name).to_uppercase(),
+            words.len(),
+            array_items(words)
+        ),
+        Format::CArray => format!(
+            "#include <stdint.h>\n\nconst uint16_t {}[{}] = {{\n{}}};\n",
+            identifier(name).to_lowercase(),
+            words.len(),
+            array_items(words)
+        ),
+    };
+    text.into_bytes()
+}
@@ -0,0 +1,408 @@
+use crate::code::{self, COMP_TABLE, DEST_TABLE, JUMP_TABLE};
+use crate::error::{suggest, AsmError, ErrorKind, Span};
+use crate::expr::{parse_number, Expr};
+use crate::preprocessor::{preprocess_partial, SourceLine};
+use crate::source::SourceMap;
+use std::fmt;
+use std::path::PathBuf;
+
+// largest value that fits the 15-bit field of an A-instruction
+pub const MAX_CONSTANT: u16 = 32767;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Constant(u16),
+    Symbol(String),
+    Expression(Expr),
+}
+
+// @value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AInstruction {
+    pub value: Value,
+    pub span: Span,
+}
+
+// dest=comp;jump in canonical spelling, with empty strings for the omitted fields
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CInstruction {
+    pub dest: String,
+    pub comp: String,
+    pub jump: String,
+    // how the instruction was written, when that differs from the canonical spelling
+    pub spelling: Option<String>,
+    pub span: Span,
+}
+
+// (name)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub name: String,
+    pub span: Span,
+}
+
+// directives the parser understands, anything else starting with `.` is an error
+pub const DIRECTIVES: [&str; 3] = [".export", ".import", ".equ"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DirectiveKind {
+    // make a label visible to other object files
+    Export(String),
+    // label defined in another object file
+    Import(String),
+    // named constant evaluated at assembly time
+    Equ(String, Expr),
+}
+
+// .name args
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Directive {
+    pub kind: DirectiveKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    A(AInstruction),
+    C(CInstruction),
+    Label(Label),
+    Directive(Directive),
+}
+
+impl Instruction {
+    pub fn span(&self) -> Span {
+        match self {
+            Instruction::A(a) => a.span,
+            Instruction::C(c) => c.span,
+            Instruction::Label(label) => label.span,
+            Instruction::Directive(directive) => directive.span,
+        }
+    }
+
+    pub fn set_span(&mut self, span: Span) {
+        match self {
+            Instruction::A(a) => a.span = span,
+            Instruction::C(c) => c.span = span,
+            Instruction::Label(label) => label.span = span,
+            Instruction::Directive(directive) => directive.span = span,
+        }
+    }
+
+    // whether the instruction occupies a word of ROM
+    pub fn is_code(&self) -> bool {
+        matches!(self, Instruction::A(_) | Instruction::C(_))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Constant(value) => write!(f, "{value}"),
+            Value::Symbol(name) => write!(f, "{name}"),
+            Value::Expression(expr) => write!(f, "{expr}"),
+        }
+    }
+}
+
+// assembly text of an instruction, as the assembler would read it back
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::A(a) => write!(f, "@{}", a.value),
+            Instruction::C(c) => write!(f, "{c}"),
+            Instruction::Label(label) => write!(f, "({})", label.name),
+            Instruction::Directive(directive) => match &directive.kind {
+                DirectiveKind::Export(name) => write!(f, ".export {name}"),
+                DirectiveKind::Import(name) => write!(f, ".import {name}"),
+                DirectiveKind::Equ(name, expr) => write!(f, ".equ {name} {expr}"),
+            },
+        }
+    }
+}
+
+impl fmt::Display for CInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.dest.is_empty() {
+            write!(f, "{}=", self.dest)?;
+        }
+        write!(f, "{}", self.comp)?;
+        if !self.jump.is_empty() {
+            write!(f, ";{}", self.jump)?;
+        }
+        Ok(())
+    }
+}
+
+impl CInstruction {
+    pub fn encode(&self) -> u16 {
+        // mnemonics are validated while parsing
+        code::encode_c(
+            code::comp(&self.comp).unwrap(),
+            code::dest(&self.dest).unwrap(),
+            code::jump(&self.jump).unwrap(),
+        )
+    }
+}
+
+// drop a trailing `//` comment
+pub(crate) fn strip_comment(line: &str) -> &str {
+    line.find("//").map_or(line, |idx| &line[..idx])
+}
+
+// trim a field of a line, returning it with the 0-based offset it starts at
+fn trim_field(field: &str, offset: usize) -> (&str, usize) {
+    let trimmed = field.trim_start();
+    (trimmed.trim_end(), offset + field.len() - trimmed.len())
+}
+
+fn parse_label(line: &str, line_number: usize, indent: usize) -> Result<Instruction, AsmError> {
+    let label = line.strip_prefix("(").and_then(|l| l.strip_suffix(")"));
+    match label.map(|name| trim_field(name, indent + 1)) {
+        Some((name, offset))
+            if !name.is_empty()
+                && !name.contains(['(', ')'])
+                && !name.contains(char::is_whitespace) =>
+        {
+            Ok(Instruction::Label(Label {
+                name: name.to_string(),
+                span: Span::new(line_number, offset + 1, name.len()),
+            }))
+        }
+        _ => Err(AsmError::new(
+            ErrorKind::InvalidLabel(line.to_string()),
+            Span::new(line_number, indent + 1, line.len()),
+        )),
+    }
+}
+
+pub(crate) fn is_valid_symbol(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$' | ':'))
+}
+
+fn parse_directive(line: &str, line_number: usize, indent: usize) -> Result<Instruction, AsmError> {
+    let span = Span::new(line_number, indent + 1, line.len());
+    let mut parts = line.split_whitespace();
+    let name = parts.next().unwrap_or("");
+    let args: Vec<&str> = parts.collect();
+
+    let kind = match (name, args.as_slice()) {
+        (".export", [symbol]) if is_valid_symbol(symbol) => {
+            DirectiveKind::Export(symbol.to_string())
+        }
+        (".import", [symbol]) if is_valid_symbol(symbol) => {
+            DirectiveKind::Import(symbol.to_string())
+        }
+        (".equ", [symbol, ..]) if is_valid_symbol(symbol) => {
+            let text = line[name.len()..].trim_start()[symbol.len()..].trim();
+            match Expr::parse(text) {
+                Some(expr) => DirectiveKind::Equ(symbol.to_string(), expr),
+                None => {
+                    return Err(AsmError::new(
+                        ErrorKind::InvalidExpression(text.to_string()),
+                        span,
+                    ))
+                }
+            }
+        }
+        _ if DIRECTIVES.contains(&name) => {
+            return Err(AsmError::new(
+                ErrorKind::InvalidDirective(line.to_string()),
+                span,
+            ));
+        }
+        _ => {
+            return Err(AsmError::new(
+                ErrorKind::UnknownDirective(name.to_string()),
+                span,
+            ))
+        }
+    };
+    Ok(Instruction::Directive(Directive { kind, span }))
+}
+
+fn parse_a(value: &str, line_number: usize, indent: usize) -> Result<Instruction, AsmError> {
+    let (value, offset) = trim_field(value, indent + 1);
+    let span = Span::new(line_number, offset + 1, value.len());
+    if value.is_empty() {
+        return Err(AsmError::new(
+            ErrorKind::EmptySymbol,
+            Span { len: 1, ..span },
+        ));
+    }
+
+    let value = if let Some(constant) = parse_number(value) {
+        if !(0..=MAX_CONSTANT as i64).contains(&constant) {
+            return Err(AsmError::new(
+                ErrorKind::ConstantOutOfRange(value.to_string()),
+                span,
+            ));
+        }
+        Value::Constant(constant as u16)
+    } else if is_valid_symbol(value) {
+        Value::Symbol(value.to_string())
+    } else if let Some(expr) = Expr::parse(value) {
+        Value::Expression(expr)
+    } else {
+        return Err(AsmError::new(
+            ErrorKind::InvalidExpression(value.to_string()),
+            span,
+        ));
+    };
+    Ok(Instruction::A(AInstruction { value, span }))
+}
+
+fn parse_c(
+    line: &str,
+    line_number: usize,
+    indent: usize,
+    errors: &mut Vec<AsmError>,
+) -> Option<Instruction> {
+    let (dest, rest) = line.split_once('=').unwrap_or(("", line));
+    let rest_offset = indent + line.len() - rest.len();
+    let (comp, jump) = rest.split_once(';').unwrap_or((rest, ""));
+    let (dest, dest_offset) = trim_field(dest, indent);
+    let (comp, comp_offset) = trim_field(comp, rest_offset);
+    let (jump, jump_offset) = trim_field(jump, rest_offset + rest.len() - jump.len());
+
+    let canonical_dest = code::canonical_dest(dest);
+    let canonical_comp = code::canonical_comp(comp);
+    let canonical_jump = code::canonical_jump(jump);
+
+    if canonical_dest.is_none() {
+        let span = Span::new(line_number, dest_offset + 1, dest.len());
+        let suggestion = suggest(dest, code::mnemonics(&DEST_TABLE));
+        errors.push(
+            AsmError::new(ErrorKind::UnknownDest(dest.to_string()), span)
+                .with_suggestion(suggestion),
+        );
+    }
+    if comp.is_empty() {
+        let span = Span::new(line_number, comp_offset + 1, 1);
+        errors.push(AsmError::new(ErrorKind::MissingComp, span));
+    } else if canonical_comp.is_none() {
+        let span = Span::new(line_number, comp_offset + 1, comp.len());
+        let suggestion = suggest(comp, code::mnemonics(&COMP_TABLE));
+        errors.push(
+            AsmError::new(ErrorKind::UnknownComp(comp.to_string()), span)
+                .with_suggestion(suggestion),
+        );
+    }
+    if canonical_jump.is_none() {
+        let span = Span::new(line_number, jump_offset + 1, jump.len());
+        let suggestion = suggest(jump, code::mnemonics(&JUMP_TABLE));
+        errors.push(
+            AsmError::new(ErrorKind::UnknownJump(jump.to_string()), span)
+                .with_suggestion(suggestion),
+        );
+    }
+
+    let (Some(canonical_dest), Some(canonical_comp), Some(canonical_jump)) =
+        (canonical_dest, canonical_comp, canonical_jump)
+    else {
+        return None;
+    };
+
+    // whitespace is not a spelling difference
+    let written: Vec<String> = [dest, comp, jump]
+        .iter()
+        .map(|field| field.split_whitespace().collect())
+        .collect();
+    let is_canonical = written == [canonical_dest, canonical_comp, canonical_jump];
+    Some(Instruction::C(CInstruction {
+        dest: canonical_dest.to_string(),
+        comp: canonical_comp.to_string(),
+        jump: canonical_jump.to_string(),
+        spelling: (!is_canonical).then(|| line.to_string()),
+        span: Span::new(line_number, indent + 1, line.len()),
+    }))
+}
+
+// parse a whole assembly source, collecting every error instead of stopping at the first
+pub fn parse(source: &str) -> Result<Vec<Instruction>, Vec<AsmError>> {
+    parse_with(&mut SourceMap::from_source("<source>", source), &[])
+}
+
+// parse file 0 of `sources`, resolving `.include`s against its directory and `include_dirs`
+pub fn parse_with(
+    sources: &mut SourceMap,
+    include_dirs: &[PathBuf],
+) -> Result<Vec<Instruction>, Vec<AsmError>> {
+    // a preprocessor error does not hide the parse errors of the other lines
+    let (lines, mut errors) = preprocess_partial(sources, include_dirs);
+    match parse_lines(&lines) {
+        Ok(instructions) if errors.is_empty() => Ok(instructions),
+        Ok(_) => Err(errors),
+        Err(parse_errors) => {
+            errors.extend(parse_errors);
+            errors.sort_by_key(|error| (error.span.file, error.span.line));
+            Err(errors)
+        }
+    }
+}
+
+// warnings for C-instructions that were not written in their canonical spelling
+pub fn spelling_warnings(instructions: &[Instruction]) -> Vec<AsmError> {
+    instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::C(c) => c.spelling.as_ref().map(|written| {
+                let kind = ErrorKind::NonCanonical {
+                    written: written.clone(),
+                    canonical: c.to_string(),
+                };
+                AsmError::new(kind, c.span)
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn parse_lines(lines: &[SourceLine]) -> Result<Vec<Instruction>, Vec<AsmError>> {
+    let mut instructions = vec![];
+    let mut errors = vec![];
+
+    for source_line in lines {
+        let line_number = source_line.line;
+        let code = strip_comment(&source_line.text);
+        let indent = code.len() - code.trim_start().len();
+        let line = code.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let error_count = errors.len();
+        let instruction = if line.starts_with("(") {
+            parse_label(line, line_number, indent).map_err(Some)
+        } else if line.starts_with(".") {
+            parse_directive(line, line_number, indent).map_err(Some)
+        } else if let Some(value) = line.strip_prefix("@") {
+            parse_a(value, line_number, indent).map_err(Some)
+        } else {
+            // C-instructions push their own errors, one per bad field
+            parse_c(line, line_number, indent, &mut errors).ok_or(None)
+        };
+
+        // lines from a macro expansion point at the invocation
+        let span_of = |span: Span| source_line.origin.unwrap_or(span.in_file(source_line.file));
+        match instruction {
+            Ok(mut instruction) => {
+                instruction.set_span(span_of(instruction.span()));
+                instructions.push(instruction);
+            }
+            Err(error) => errors.extend(error),
+        }
+        for error in &mut errors[error_count..] {
+            error.span = span_of(error.span);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(instructions)
+    } else {
+        Err(errors)
+    }
+}
@@ -0,0 +1,382 @@
+use crate::code;
+use crate::error::{AsmError, ErrorKind, Span};
+use crate::parser::{is_valid_symbol, strip_comment, DIRECTIVES};
+use crate::source::SourceMap;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// pseudo-instructions available to every program, written as ordinary macros
+const BUILTIN_MACROS: &str = "
+.macro PUSHD
+@SP
+AM=M+1
+A=A-1
+M=D
+.endm
+.macro POPD
+@SP
+AM=M-1
+D=M
+.endm
+.macro GOTO target
+@%target
+0;JMP
+.endm
+.macro IFEQ target
+@%target
+D;JEQ
+.endm
+.macro IFNE target
+@%target
+D;JNE
+.endm
+.macro IFGT target
+@%target
+D;JGT
+.endm
+.macro IFGE target
+@%target
+D;JGE
+.endm
+.macro IFLT target
+@%target
+D;JLT
+.endm
+.macro IFLE target
+@%target
+D;JLE
+.endm
+";
+
+// nested invocations deeper than this are assumed to be recursive
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+// a line of assembly after preprocessing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    pub text: String,
+    pub line: usize,
+    // index into the SourceMap of the file the line was read from
+    pub file: usize,
+    // invocation a line was expanded from, errors and listings point there
+    pub origin: Option<Span>,
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$' | ':')
+}
+
+fn split_args(args: &str) -> Vec<&str> {
+    args.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|arg| !arg.is_empty())
+        .collect()
+}
+
+// file name of an `.include "file.asm"` line
+fn include_name(line: &str) -> Option<&str> {
+    let name = line[".include".len()..]
+        .trim()
+        .strip_prefix('"')?
+        .strip_suffix('"')?;
+    (!name.is_empty() && !name.contains('"')).then_some(name)
+}
+
+#[derive(Default)]
+struct Preprocessor {
+    macros: HashMap<String, Macro>,
+    expansion_idx: usize,
+    errors: Vec<AsmError>,
+    // searched after the directory of the including file
+    include_dirs: Vec<PathBuf>,
+    // canonical and display paths of the files currently being included, to detect cycles
+    include_stack: Vec<(PathBuf, String)>,
+}
+
+impl Preprocessor {
+    fn error(&mut self, kind: ErrorKind, span: Span) {
+        self.errors.push(AsmError::new(kind, span));
+    }
+
+    // first match for an included file, next to the including file or in the include path
+    fn find_include(&self, name: &str, including: Option<&Path>) -> Option<PathBuf> {
+        let base = including
+            .and_then(Path::parent)
+            .map_or_else(PathBuf::new, Path::to_path_buf);
+        std::iter::once(base)
+            .chain(self.include_dirs.iter().cloned())
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    }
+
+    // read an included file into the source map and collect its lines
+    fn include(
+        &mut self,
+        line: &str,
+        file: usize,
+        span: Span,
+        sources: &mut SourceMap,
+    ) -> Vec<SourceLine> {
+        let Some(name) = include_name(line) else {
+            self.error(ErrorKind::InvalidInclude(line.to_string()), span);
+            return vec![];
+        };
+        let Some(path) = self.find_include(name, sources.files[file].path.as_deref()) else {
+            self.error(ErrorKind::IncludeNotFound(name.to_string()), span);
+            return vec![];
+        };
+
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        let name = path.display().to_string();
+        if let Some(start) = self.include_stack.iter().position(|(p, _)| *p == canonical) {
+            let chain: Vec<&str> = self.include_stack[start..]
+                .iter()
+                .map(|(_, name)| name.as_str())
+                .chain([name.as_str()])
+                .collect();
+            self.error(ErrorKind::IncludeCycle(chain.join(" -> ")), span);
+            return vec![];
+        }
+
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) => {
+                let kind = ErrorKind::IncludeRead {
+                    path: name,
+                    reason: e.to_string(),
+                };
+                self.error(kind, span);
+                return vec![];
+            }
+        };
+        let included = sources.add(&name, Some(path), source.clone(), Some(span));
+
+        self.include_stack.push((canonical, name));
+        let lines = self.define_macros(&source, included, sources);
+        self.include_stack.pop();
+        lines
+    }
+
+    // collect `.macro` definitions, splice in `.include`d files and pass every other line through
+    fn define_macros(
+        &mut self,
+        source: &str,
+        file: usize,
+        sources: &mut SourceMap,
+    ) -> Vec<SourceLine> {
+        let mut lines = vec![];
+        let mut current: Option<(String, Macro, Span)> = None;
+
+        for (line_idx, raw_line) in source.lines().enumerate() {
+            let line_number = line_idx + 1;
+            let code = strip_comment(raw_line);
+            let indent = code.len() - code.trim_start().len();
+            let line = code.trim();
+            let span = Span::new(line_number, indent + 1, line.len()).in_file(file);
+            let directive = line.split_whitespace().next().unwrap_or("");
+
+            match (directive, current.as_mut()) {
+                (".include", None) => lines.extend(self.include(line, file, span, sources)),
+                (".include", Some(_)) => self.error(ErrorKind::IncludeInMacro, span),
+                (".macro", None) => {
+                    let mut params = split_args(&line[".macro".len()..]).into_iter();
+                    let name = params.next().unwrap_or("").to_string();
+                    let params: Vec<String> = params.map(String::from).collect();
+                    if !is_valid_symbol(&name) || code::comp(&name).is_some() {
+                        self.error(ErrorKind::InvalidMacroName(name.clone()), span);
+                    } else if self.macros.contains_key(&name) {
+                        self.error(ErrorKind::DuplicateMacro(name.clone()), span);
+                    }
+                    let body = vec![];
+                    current = Some((name, Macro { params, body }, span));
+                }
+                (".macro", Some(_)) => self.error(ErrorKind::NestedMacro, span),
+                (".endm", Some(_)) => {
+                    let (name, definition, _) = current.take().unwrap();
+                    self.macros.entry(name).or_insert(definition);
+                }
+                (".endm", None) => self.error(ErrorKind::UnexpectedEndm, span),
+                (_, Some((_, definition, _))) => {
+                    if !line.is_empty() {
+                        definition.body.push(line.to_string());
+                    }
+                }
+                (_, None) => lines.push(SourceLine {
+                    text: raw_line.to_string(),
+                    line: line_number,
+                    file,
+                    origin: None,
+                }),
+            }
+        }
+
+        if let Some((name, _, span)) = current {
+            self.error(ErrorKind::UnterminatedMacro(name), span);
+        }
+        lines
+    }
+
+    // substitute `%param` arguments and `%%label` local labels in a body line, a
+    // `%` after an operand is the remainder operator and is kept as written
+    fn substitute(
+        &self,
+        line: &str,
+        name: &str,
+        expansion_idx: usize,
+        bindings: &HashMap<&str, &str>,
+    ) -> Result<String, ErrorKind> {
+        let mut out = String::new();
+        let mut rest = line;
+        while let Some(idx) = rest.find('%') {
+            out += &rest[..idx];
+            rest = &rest[idx + 1..];
+
+            let local = rest.strip_prefix('%').unwrap_or(rest);
+            let is_local = local.len() < rest.len();
+            let end = local.find(|c| !is_symbol_char(c)).unwrap_or(local.len());
+            let ident = &local[..end];
+            let is_label = !ident.is_empty() && !ident.starts_with(|c: char| c.is_ascii_digit());
+
+            if is_local && is_label {
+                out += &format!("{name}${ident}.{expansion_idx}");
+                rest = &local[end..];
+            } else if let Some(value) = bindings.get(ident).filter(|_| !is_local) {
+                out += value;
+                rest = &local[end..];
+            } else if out
+                .trim_end()
+                .ends_with(|c: char| is_symbol_char(c) || c == ')')
+            {
+                out.push('%');
+            } else {
+                return Err(ErrorKind::UnknownMacroParam(ident.to_string()));
+            }
+        }
+        out += rest;
+        Ok(out)
+    }
+
+    fn expand(
+        &mut self,
+        text: &str,
+        line: usize,
+        file: usize,
+        origin: Option<Span>,
+        depth: usize,
+        out: &mut Vec<SourceLine>,
+    ) {
+        let code = strip_comment(text);
+        let indent = code.len() - code.trim_start().len();
+        let trimmed = code.trim();
+        let span = origin.unwrap_or(Span::new(line, indent + 1, trimmed.len()).in_file(file));
+        let (name, args) = trimmed
+            .split_once(char::is_whitespace)
+            .unwrap_or((trimmed, ""));
+
+        let Some(definition) = self.macros.get(name).cloned() else {
+            if trimmed.starts_with('.') && !DIRECTIVES.contains(&name) {
+                self.error(ErrorKind::UnknownDirective(name.to_string()), span);
+            } else {
+                let text = text.to_string();
+                out.push(SourceLine {
+                    text,
+                    line,
+                    file,
+                    origin,
+                });
+            }
+            return;
+        };
+
+        if depth >= MAX_EXPANSION_DEPTH {
+            self.error(ErrorKind::MacroRecursion(name.to_string()), span);
+            return;
+        }
+
+        let args = split_args(args);
+        if args.len() != definition.params.len() {
+            let kind = ErrorKind::MacroArity {
+                name: name.to_string(),
+                expected: definition.params.len(),
+                found: args.len(),
+            };
+            self.error(kind, span);
+            return;
+        }
+
+        let bindings: HashMap<&str, &str> = definition
+            .params
+            .iter()
+            .map(String::as_str)
+            .zip(args)
+            .collect();
+
+        // every expansion gets its own copy of the local labels
+        let expansion_idx = self.expansion_idx;
+        self.expansion_idx += 1;
+        for body_line in &definition.body {
+            match self.substitute(body_line, name, expansion_idx, &bindings) {
+                Ok(expanded) => {
+                    self.expand(&expanded, span.line, span.file, Some(span), depth + 1, out)
+                }
+                Err(kind) => self.error(kind, span),
+            }
+        }
+    }
+}
+
+// expand macro definitions and invocations into plain assembly lines
+pub fn preprocess(source: &str) -> Result<Vec<SourceLine>, Vec<AsmError>> {
+    preprocess_with(&mut SourceMap::from_source("<source>", source), &[])
+}
+
+// preprocess file 0 of `sources`, adding every `.include`d file to the map
+pub fn preprocess_with(
+    sources: &mut SourceMap,
+    include_dirs: &[PathBuf],
+) -> Result<Vec<SourceLine>, Vec<AsmError>> {
+    let (lines, errors) = preprocess_partial(sources, include_dirs);
+    if errors.is_empty() {
+        Ok(lines)
+    } else {
+        Err(errors)
+    }
+}
+
+// like `preprocess_with`, but also returns the lines that did preprocess when
+// there are errors, so the parser can still report the problems in them
+pub fn preprocess_partial(
+    sources: &mut SourceMap,
+    include_dirs: &[PathBuf],
+) -> (Vec<SourceLine>, Vec<AsmError>) {
+    let mut preprocessor = Preprocessor {
+        include_dirs: include_dirs.to_vec(),
+        ..Default::default()
+    };
+    preprocessor.define_macros(BUILTIN_MACROS, 0, sources);
+
+    let root = &sources.files[0];
+    if let Some(path) = &root.path {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        preprocessor
+            .include_stack
+            .push((canonical, root.name.clone()));
+    }
+    let source = root.source.clone();
+    let lines = preprocessor.define_macros(&source, 0, sources);
+
+    let mut out = vec![];
+    for source_line in lines {
+        let SourceLine {
+            text, line, file, ..
+        } = source_line;
+        preprocessor.expand(&text, line, file, None, 0, &mut out);
+    }
+
+    (out, preprocessor.errors)
+}
@@ -0,0 +1,67 @@
+use crate::error::{AsmError, Span};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    // name shown in diagnostics
+    pub name: String,
+    // location on disk, used to resolve includes relative to the file
+    pub path: Option<PathBuf>,
+    pub source: String,
+    // the .include line that pulled this file in
+    pub included_from: Option<Span>,
+}
+
+// every file taking part in an assembly, indexed by Span::file
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    pub files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    // a map holding a single in-memory source as file 0
+    pub fn from_source(name: &str, source: &str) -> Self {
+        let mut sources = SourceMap::default();
+        sources.add(name, None, source.to_string(), None);
+        sources
+    }
+
+    pub fn add(
+        &mut self,
+        name: &str,
+        path: Option<PathBuf>,
+        source: String,
+        included_from: Option<Span>,
+    ) -> usize {
+        self.files.push(SourceFile {
+            name: name.to_string(),
+            path,
+            source,
+            included_from,
+        });
+        self.files.len() - 1
+    }
+
+    pub fn line(&self, span: Span) -> &str {
+        self.files
+            .get(span.file)
+            .and_then(|file| file.source.lines().nth(span.line.saturating_sub(1)))
+            .unwrap_or("")
+    }
+
+    // diagnostic for the file the error is in, followed by the chain of includes leading there
+    pub fn render(&self, error: &AsmError) -> String {
+        let Some(file) = self.files.get(error.span.file) else {
+            return format!("error: {}\n", error.kind);
+        };
+        let mut out = error.render(&file.name, &file.source);
+
+        let mut included_from = file.included_from;
+        while let Some(span) = included_from {
+            let including = &self.files[span.file];
+            out += &format!("  = note: included from {}:{}\n", including.name, span.line);
+            included_from = including.included_from;
+        }
+        out
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+// R0-15 are reserved, variables are allocated from here upwards
+pub const VARIABLE_BASE: u16 = 16;
+
+// last RAM address before SCREEN memory
+pub const VARIABLE_LIMIT: u16 = 16383;
+
+const PREDEFINED: [(&str, u16); 23] = [
+    ("SP", 0),
+    ("LCL", 1),
+    ("ARG", 2),
+    ("THIS", 3),
+    ("THAT", 4),
+    ("R0", 0),
+    ("R1", 1),
+    ("R2", 2),
+    ("R3", 3),
+    ("R4", 4),
+    ("R5", 5),
+    ("R6", 6),
+    ("R7", 7),
+    ("R8", 8),
+    ("R9", 9),
+    ("R10", 10),
+    ("R11", 11),
+    ("R12", 12),
+    ("R13", 13),
+    ("R14", 14),
+    ("R15", 15),
+    ("SCREEN", 16384),
+    ("KBD", 24576),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Predefined,
+    // ROM address of a (label) declaration
+    Label,
+    // RAM address allocated for an unknown @symbol
+    Variable,
+    // value of a .equ directive
+    Constant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol {
+    pub address: u16,
+    pub kind: SymbolKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct SymbolTable {
+    symbols: HashMap<String, Symbol>,
+    next_variable: u16,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        let predefined = |address| Symbol {
+            address,
+            kind: SymbolKind::Predefined,
+        };
+        SymbolTable {
+            symbols: PREDEFINED
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), predefined(value)))
+                .collect(),
+            next_variable: VARIABLE_BASE,
+        }
+    }
+
+    fn insert(&mut self, name: &str, address: u16, kind: SymbolKind) {
+        self.symbols
+            .insert(name.to_string(), Symbol { address, kind });
+    }
+
+    // fails with the existing symbol when the name is already taken
+    pub fn add_label(&mut self, name: &str, address: u16) -> Result<(), Symbol> {
+        if let Some(existing) = self.symbol(name) {
+            return Err(existing);
+        }
+        self.insert(name, address, SymbolKind::Label);
+        Ok(())
+    }
+
+    // fails with the existing symbol when the name is already taken
+    pub fn add_constant(&mut self, name: &str, value: u16) -> Result<(), Symbol> {
+        if let Some(existing) = self.symbol(name) {
+            return Err(existing);
+        }
+        self.insert(name, value, SymbolKind::Constant);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<u16> {
+        self.symbols.get(name).map(|symbol| symbol.address)
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).copied()
+    }
+
+    // look a symbol up, allocating it as a new variable if it is not present.
+    // returns None once variables would run into SCREEN memory
+    pub fn get_or_allocate(&mut self, name: &str) -> Option<u16> {
+        if let Some(address) = self.get(name) {
+            return Some(address);
+        }
+        if self.next_variable > VARIABLE_LIMIT {
+            return None;
+        }
+        let address = self.next_variable;
+        self.insert(name, address, SymbolKind::Variable);
+        self.next_variable += 1;
+        Some(address)
+    }
+
+    // symbols of one kind, ordered by address then name
+    pub fn of_kind(&self, kind: SymbolKind) -> Vec<(&str, u16)> {
+        let mut symbols: Vec<(&str, u16)> = self
+            .symbols
+            .iter()
+            .filter(|(_, symbol)| symbol.kind == kind)
+            .map(|(name, symbol)| (name.as_str(), symbol.address))
+            .collect();
+        symbols.sort_by_key(|(name, address)| (*address, *name));
+        symbols
+    }
+}
@@ -1,3 +1,5 @@
+use crate::error::LineError;
+
 // A = *SP
 const DEREF_SP: &str = "@SP\nA=M";
 
@@ -13,36 +15,128 @@ const STORE_TEMP: &str = "@R13\nM=D";
 // D = R13
 const GET_TEMP: &str = "@R13\nD=M";
 
-fn get_segment_pointer(segment: &str) -> &str {
+// shared routines of compact mode, the caller puts the return address in R13,
+// the argument count in R14 and the function address in D
+const CALL_ROUTINE: &str = "$$CALL";
+const RETURN_ROUTINE: &str = "$$RETURN";
+const START: &str = "$$START";
+
+fn get_segment_pointer(segment: &str) -> Option<&'static str> {
     match segment {
-        "local" => "LCL",
-        "argument" => "ARG",
-        "this" => "THIS",
-        "that" => "THAT",
-        _ => panic!("Unknown segment: {segment}"),
+        "local" => Some("LCL"),
+        "argument" => Some("ARG"),
+        "this" => Some("THIS"),
+        "that" => Some("THAT"),
+        _ => None,
     }
 }
 
-fn handle_comparison(command: &str, jump_idx: u32) -> String {
-    let jump_type = match command {
-        "eq" => "JEQ",
-        "lt" => "JGT",
-        "gt" => "JLT",
-        _ => panic!("unknown command: {command}"),
+// expects D = y and A = address of x, leaves x (op) y at that address, where
+// `jump_type` is JEQ, JLT or JGT
+fn handle_comparison(jump_type: &str, file_name: &str, jump_idx: u32) -> String {
+    // D = x - y, which overflows when the operands have opposite signs, so for
+    // lt and gt D is only set to the sign of x - y in that case
+    let difference = if jump_type == "JEQ" {
+        "D=M-D".to_string()
+    } else {
+        [
+            STORE_TEMP, // R13 = y
+            DEREF_SP,
+            "D=M", // D = x
+            &format!("@SYS.{file_name}.XNEG{jump_idx}\nD;JLT"),
+            // x >= 0
+            GET_TEMP,
+            &format!("@SYS.{file_name}.SAMESIGN{jump_idx}\nD;JGE"),
+            "D=1", // x >= 0 > y
+            &format!("@SYS.{file_name}.COMPARE{jump_idx}\n0;JMP"),
+            &format!("(SYS.{file_name}.XNEG{jump_idx})"),
+            GET_TEMP,
+            &format!("@SYS.{file_name}.SAMESIGN{jump_idx}\nD;JLT"),
+            "D=-1", // x < 0 <= y
+            &format!("@SYS.{file_name}.COMPARE{jump_idx}\n0;JMP"),
+            &format!("(SYS.{file_name}.SAMESIGN{jump_idx})"),
+            GET_TEMP,
+            DEREF_SP,
+            "D=M-D", // cannot overflow
+            &format!("(SYS.{file_name}.COMPARE{jump_idx})"),
+        ]
+        .join("\n")
     };
 
-    let sys_continue = &format!("@SYS.CONTINUE{jump_idx}\n0;JMP");
+    let sys_continue = &format!("@SYS.{file_name}.CONTINUE{jump_idx}\n0;JMP");
     let asm = [
-        &format!("D=D-M\n@SYS.JUMP{jump_idx}\nD;{jump_type}\n"), // comparison
+        &difference,
+        &format!("@SYS.{file_name}.JUMP{jump_idx}\nD;{jump_type}"), // comparison
         DEREF_SP,
         "M=0", // false
         sys_continue,
-        &format!("(SYS.JUMP{jump_idx})"), // jump
+        &format!("(SYS.{file_name}.JUMP{jump_idx})"), // jump
         DEREF_SP,
         "M=-1", // true
         sys_continue,
-        &format!("(SYS.CONTINUE{jump_idx})"), // continue
+        &format!("(SYS.{file_name}.CONTINUE{jump_idx})"), // continue
+    ];
+    asm.join("\n")
+}
+
+// push D, then SP++
+fn push_d() -> String {
+    [DEREF_SP, "M=D", INCREMENT_SP].join("\n")
+}
+
+// save the caller's frame and jump to the function in R15, with the return
+// address in R13 and the argument count in R14
+fn call_routine() -> String {
+    let mut asm = vec![format!("({CALL_ROUTINE})"), "@R15\nM=D".to_string()];
+    asm.push(format!("{GET_TEMP}\n{}", push_d()));
+    for segment in ["@LCL", "@ARG", "@THIS", "@THAT"] {
+        asm.push(format!("{segment}\nD=M\n{}", push_d()));
+    }
+    // ARG = SP - 5 - nArgs
+    asm.push("@SP\nD=M\n@5\nD=D-A\n@R14\nD=D-M\n@ARG\nM=D".to_string());
+    asm.push("@R15\nA=M\n0;JMP".to_string());
+    asm.join("\n")
+}
+
+// restore the caller's frame and jump to its return address
+fn return_routine() -> String {
+    let mut asm = vec![
+        // D = *LCL
+        "@LCL\nA=M\nD=A",
+        // D = *(LCL - 5), which is the return address
+        "@5\nA=D-A\nD=M",
+        // store return address to temp variable
+        STORE_TEMP,
+        // copy return value to *ARG, which will be at the top of the stack when function ends
+        DECREMENT_SP,
+        DEREF_SP,
+        "D=M",
+        // *ARG = D
+        "@ARG\nA=M\nM=D",
     ];
+
+    // SP = @ARG + 1
+    asm.push("@ARG\nD=M");
+    asm.push("@SP\nM=D");
+    asm.push(INCREMENT_SP);
+
+    // restore caller's original memory segment pointers
+    let labels = ["@THAT", "@THIS", "@ARG", "@LCL"];
+    for label in labels.iter() {
+        // LCL--
+        asm.push("@LCL\nM=M-1");
+        // D = *LCL
+        asm.push("A=M\nD=M");
+        // A = @label
+        asm.push(label);
+        // label = D
+        asm.push("M=D")
+    }
+
+    // jump to return address
+    asm.push(GET_TEMP);
+    asm.push("A=D\n0;JMP");
+
     asm.join("\n")
 }
 
@@ -50,36 +144,72 @@ pub struct CodeWriter {
     file_name: String,
     jump_idx: u32,
     call_idx: u32,
+    // function being translated, which scopes its labels
+    function: Option<String>,
+    // calls and returns jump to shared routines instead of being inlined
+    compact: bool,
 }
 
 impl CodeWriter {
-    pub fn new(file_name: String) -> Self {
+    pub fn new(file_name: String, compact: bool) -> Self {
         CodeWriter {
             file_name,
             jump_idx: 0,
             call_idx: 0,
+            function: None,
+            compact,
         }
     }
 
     pub fn booting_code(&mut self) -> String {
         // set SP = 256 then call Sys.init
         let call_sys_init = self.handle_function_call("Sys.init", 0);
-        format!("@256\nD=A\n@SP\nM=D\n{call_sys_init}")
+        let boot = format!("@256\nD=A\n@SP\nM=D\n{call_sys_init}");
+        if self.compact {
+            // Sys.init never returns, so the shared routines can follow it
+            format!(
+                "{boot}\n{}\n({RETURN_ROUTINE})\n{}",
+                call_routine(),
+                return_routine()
+            )
+        } else {
+            boot
+        }
     }
 
-    pub fn handle_arithmetic(&mut self, command: &str) -> String {
-        let operation = match command {
-            "add" => "M=D+M",
-            "sub" => "M=D-M\nM=-M",
-            "and" => "M=D&M",
-            "or" => "M=D|M",
-            "neg" => "M=-M",
-            "not" => "M=!M",
-            "eq" | "lt" | "gt" => {
+    // programs without bootstrap code start at ROM[0], so compact mode jumps
+    // over the shared routines there
+    pub fn shared_routines(&self) -> String {
+        if self.compact {
+            format!(
+                "@{START}\n0;JMP\n{}\n({RETURN_ROUTINE})\n{}\n({START})",
+                call_routine(),
+                return_routine()
+            )
+        } else {
+            String::new()
+        }
+    }
+
+    pub fn handle_arithmetic(&mut self, command: &str) -> Result<String, LineError> {
+        let jump_type = match command {
+            "eq" => Some("JEQ"),
+            "lt" => Some("JLT"),
+            "gt" => Some("JGT"),
+            _ => None,
+        };
+        let operation = match (command, jump_type) {
+            (_, Some(jump_type)) => {
                 self.jump_idx += 1;
-                &handle_comparison(command, self.jump_idx)
+                &handle_comparison(jump_type, &self.file_name, self.jump_idx)
             }
-            _ => panic!("unknown command: {command}"),
+            ("add", _) => "M=D+M",
+            ("sub", _) => "M=D-M\nM=-M",
+            ("and", _) => "M=D&M",
+            ("or", _) => "M=D|M",
+            ("neg", _) => "M=-M",
+            ("not", _) => "M=!M",
+            _ => return Err(LineError::UnknownCommand(command.to_string())),
         };
 
         let second_operand = if matches!(command, "neg" | "not") {
@@ -95,33 +225,45 @@ impl CodeWriter {
             operation,
             INCREMENT_SP,
         ];
-        asm.join("\n")
+        Ok(asm.join("\n"))
     }
 
-    pub fn handle_memory_access(&self, command: &str, segment: &str, index: u16) -> String {
+    pub fn handle_memory_access(
+        &self,
+        command: &str,
+        segment: &str,
+        index: u16,
+    ) -> Result<String, LineError> {
+        let out_of_range = |max| LineError::IndexOutOfRange {
+            segment: segment.to_string(),
+            index,
+            max,
+        };
+
         // A = addr
-        let get_address = match segment {
-            "local" | "argument" | "this" | "that" => {
-                let segment_pointer = get_segment_pointer(segment);
+        let get_address = match (segment, get_segment_pointer(segment)) {
+            (_, Some(segment_pointer)) => {
                 // addr = segmentPointer + index
                 format!("@{segment_pointer}\nD=M\n@{index}\nA=D+A")
             }
-            "constant" => {
+            ("constant", _) => {
                 format!("@{index}")
             }
-            "static" => {
+            ("static", _) => {
                 format!("@{}.{}", self.file_name, index)
             }
-            "temp" => {
+            ("temp", _) if index > 7 => return Err(out_of_range(7)),
+            ("temp", _) => {
                 // temp variables start at address 5
                 let temp_addr = index + 5;
                 format!("@{temp_addr}")
             }
-            "pointer" => {
+            ("pointer", _) if index > 1 => return Err(out_of_range(1)),
+            ("pointer", _) => {
                 let segment_pointer = if index == 0 { "THIS" } else { "THAT" };
                 format!("@{segment_pointer}")
             }
-            _ => panic!("Unknown segment: {segment}"),
+            _ => return Err(LineError::UnknownSegment(segment.to_string())),
         };
 
         // D = *addr, except for constant where D = addr and addr is a constant literal
@@ -147,60 +289,39 @@ impl CodeWriter {
         ];
 
         let asm = if command == "push" { push_asm } else { pop_asm };
-        asm.join("\n")
+        Ok(asm.join("\n"))
     }
 
-    pub fn handle_branching(&self, command: &str, label: &str) -> String {
+    // `Function$label` as in the VM spec, labels outside of any function are
+    // scoped to the file instead
+    pub fn mangle(&self, label: &str) -> String {
+        let scope = self.function.as_deref().unwrap_or(&self.file_name);
+        format!("{scope}${label}")
+    }
+
+    pub fn handle_branching(&self, command: &str, label: &str) -> Result<String, LineError> {
+        let label = self.mangle(label);
         match command {
-            "label" => format!("({label})"),
-            "goto" => format!("@{label}\n0;JMP"),
-            "if-goto" => [DECREMENT_SP, DEREF_SP, "D=M", &format!("@{label}\nD;JNE")].join("\n"),
-            _ => panic!("Unknown command: {command}"),
+            "label" => Ok(format!("({label})")),
+            "goto" => Ok(format!("@{label}\n0;JMP")),
+            "if-goto" => {
+                Ok([DECREMENT_SP, DEREF_SP, "D=M", &format!("@{label}\nD;JNE")].join("\n"))
+            }
+            _ => Err(LineError::UnknownCommand(command.to_string())),
         }
     }
 
     pub fn handle_return(&self) -> String {
-        let mut asm = vec![
-            // D = *LCL
-            "@LCL\nA=M\nD=A",
-            // D = *(LCL - 5), which is the return address
-            "@5\nA=D-A\nD=M",
-            // store return address to temp variable
-            STORE_TEMP,
-            // copy return value to *ARG, which will be at the top of the stack when function ends
-            DECREMENT_SP,
-            DEREF_SP,
-            "D=M",
-            // *ARG = D
-            "@ARG\nA=M\nM=D",
-        ];
-
-        // SP = @ARG + 1
-        asm.push("@ARG\nD=M");
-        asm.push("@SP\nM=D");
-        asm.push(INCREMENT_SP);
-
-        // restore caller's original memory segment pointers
-        let labels = ["@THAT", "@THIS", "@ARG", "@LCL"];
-        for label in labels.iter() {
-            // LCL--
-            asm.push("@LCL\nM=M-1");
-            // D = *LCL
-            asm.push("A=M\nD=M");
-            // A = @label
-            asm.push(label);
-            // label = D
-            asm.push("M=D")
+        if self.compact {
+            format!("@{RETURN_ROUTINE}\n0;JMP")
+        } else {
+            return_routine()
         }
-
-        // jump to return address
-        asm.push(GET_TEMP);
-        asm.push("A=D\n0;JMP");
-
-        return asm.join("\n");
     }
 
-    pub fn handle_function_init(&self, name: &str, num_args: u16) -> String {
+    pub fn handle_function_init(&mut self, name: &str, num_args: u16) -> String {
+        self.function = Some(name.to_string());
+
         // function label
         let label = format!("({name})");
         let mut asm: Vec<&str> = vec![&label];
@@ -215,11 +336,23 @@ impl CodeWriter {
             asm.push(INCREMENT_SP);
         }
 
-        return asm.join("\n");
+        asm.join("\n")
     }
 
     pub fn handle_function_call(&mut self, func_name: &str, num_args: u16) -> String {
         let return_label = &format!("{}.{}.RETURN{}", self.file_name, func_name, self.call_idx);
+        if self.compact {
+            self.call_idx += 1;
+            return [
+                &format!("@{return_label}\nD=A"),
+                STORE_TEMP,
+                &format!("@{num_args}\nD=A\n@R14\nM=D"),
+                &format!("@{func_name}\nD=A"),
+                &format!("@{CALL_ROUTINE}\n0;JMP"),
+                &format!("({return_label})"),
+            ]
+            .join("\n");
+        }
         let mut asm: Vec<&str> = vec![];
         let labels = [
             &format!("@{return_label}"),
@@ -260,6 +393,6 @@ impl CodeWriter {
         asm.push(return_label);
 
         self.call_idx += 1;
-        return asm.join("\n");
+        asm.join("\n")
     }
 }
@@ -0,0 +1,122 @@
+use std::fmt;
+
+// what is wrong with a single VM command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineError {
+    UnknownCommand(String),
+    UnknownSegment(String),
+    MissingArgument {
+        command: String,
+        expected: &'static str,
+    },
+    InvalidNumber(String),
+    UnexpectedArgument(String),
+    PopConstant,
+    IndexOutOfRange {
+        segment: String,
+        index: u16,
+        max: u16,
+    },
+    DuplicateLabel {
+        label: String,
+        first_line: usize,
+    },
+    // `function` is None for labels outside of any function
+    UndefinedLabel {
+        label: String,
+        function: Option<String>,
+    },
+    DuplicateFunction {
+        name: String,
+        first: String,
+    },
+    UndefinedFunction(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmError {
+    Io {
+        path: String,
+        reason: String,
+    },
+    NoInput(String),
+    // a command of a .vm file, with its 1-based line number
+    Line {
+        file: String,
+        line: usize,
+        error: LineError,
+    },
+    // the bootstrap code calls `Sys.init`, which no file defines
+    MissingSysInit,
+    // the assembler's errors for output the optimizer could not parse
+    InvalidAssembly(String),
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LineError::UnknownCommand(command) => write!(f, "unknown command `{command}`"),
+            LineError::UnknownSegment(segment) => write!(f, "unknown segment `{segment}`"),
+            LineError::MissingArgument { command, expected } => {
+                write!(f, "expected {expected} after `{command}`")
+            }
+            LineError::InvalidNumber(value) => {
+                write!(f, "`{value}` is not a number between 0 and 65535")
+            }
+            LineError::UnexpectedArgument(argument) => {
+                write!(f, "unexpected argument `{argument}`")
+            }
+            LineError::PopConstant => write!(f, "cannot pop into the constant segment"),
+            LineError::IndexOutOfRange {
+                segment,
+                index,
+                max,
+            } => write!(
+                f,
+                "`{segment} {index}` is out of range, {segment} goes from 0 to {max}"
+            ),
+            LineError::DuplicateLabel { label, first_line } => {
+                write!(
+                    f,
+                    "label `{label}` is already declared on line {first_line}"
+                )
+            }
+            LineError::UndefinedLabel {
+                label,
+                function: Some(function),
+            } => write!(
+                f,
+                "label `{label}` is not declared in function `{function}`"
+            ),
+            LineError::UndefinedLabel {
+                label,
+                function: None,
+            } => write!(f, "label `{label}` is not declared"),
+            LineError::DuplicateFunction { name, first } => {
+                write!(f, "function `{name}` is already defined at {first}")
+            }
+            LineError::UndefinedFunction(name) => {
+                write!(f, "call to undefined function `{name}`")
+            }
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::Io { path, reason } => write!(f, "could not read `{path}`: {reason}"),
+            VmError::NoInput(path) => write!(f, "no .vm file found at `{path}`"),
+            VmError::Line { file, line, error } => write!(f, "{file}:{line}: {error}"),
+            VmError::MissingSysInit => write!(
+                f,
+                "the bootstrap code calls `Sys.init`, which is not defined, pass `--no-bootstrap` to translate a program without it"
+            ),
+            VmError::InvalidAssembly(reasons) => {
+                write!(f, "cannot optimize the generated assembly: {reasons}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
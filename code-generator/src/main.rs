@@ -1,59 +1,191 @@
 use code_writer::CodeWriter;
+use error::{LineError, VmError};
 use std::{
-    fs::{self, File},
-    io::{self, BufRead, BufReader, Write},
+    fmt::Write as _,
+    fs,
     path::{Path, PathBuf},
 };
 
 mod code_writer;
+mod error;
+mod validate;
 
-fn main() -> io::Result<()> {
-    // input file
-    let path = std::env::args().nth(1).expect("No arguments provided.");
-    let path = Path::new(&path);
-    let input_files = get_input_files(path)?;
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {program} [-O] [--compact] [--no-bootstrap] [--label-map] <file.vm|directory>"
+    );
+    std::process::exit(1);
+}
+
+fn fail(error: VmError) -> ! {
+    eprintln!("error: {error}");
+    std::process::exit(1);
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> VmError {
+    VmError::Io {
+        path: path.display().to_string(),
+        reason: error.to_string(),
+    }
+}
+
+fn main() {
+    // input file, `-O` runs the assembler's peephole optimizer over the output,
+    // `--compact` shares one call and one return routine between all call sites
+    // `--no-bootstrap` starts at the first command instead of calling `Sys.init` and
+    // `--label-map` lists where each mangled label comes from in `{stem}.labels`
+    let args: Vec<String> = std::env::args().collect();
+    let optimize = args.iter().any(|arg| arg == "-O" || arg == "--optimize");
+    let compact = args.iter().any(|arg| arg == "--compact");
+    let bootstrap = !args.iter().any(|arg| arg == "--no-bootstrap");
+    let label_map = args.iter().any(|arg| arg == "--label-map");
+    let Some(path) = args.iter().skip(1).find(|arg| !arg.starts_with('-')) else {
+        usage(&args[0]);
+    };
+    let path = Path::new(path);
+    let input_files = get_input_files(path).unwrap_or_else(|e| fail(io_error(path, e)));
 
-    if input_files.len() <= 0 {
-        panic!("No .vm file provided");
+    if input_files.is_empty() {
+        fail(VmError::NoInput(path.display().to_string()));
     }
 
-    // output file
-    let stem = path.file_stem().unwrap().to_str().unwrap();
+    let files: Vec<(PathBuf, String)> = input_files
+        .into_iter()
+        .map(|file| {
+            let source = fs::read_to_string(&file).unwrap_or_else(|e| fail(io_error(&file, e)));
+            (file, source)
+        })
+        .collect();
+
+    // output file, named after the directory when translating `.`
+    let canonical = path
+        .canonicalize()
+        .unwrap_or_else(|e| fail(io_error(path, e)));
+    let stem = &file_stem(&canonical);
     let output_file_name = format!("{stem}.asm");
-    let mut output_file = File::create(output_file_name).unwrap();
+    let (mut output, labels) =
+        translate(&files, stem, compact, bootstrap).unwrap_or_else(|errors| {
+            for error in &errors {
+                eprintln!("error: {error}");
+            }
+            eprintln!(
+                "error: could not translate `{}` due to {} previous error(s)",
+                path.display(),
+                errors.len()
+            );
+            std::process::exit(1);
+        });
 
-    // booting code
-    let mut boot_code_writer = CodeWriter::new(stem.to_string());
-    writeln!(output_file, "{}", boot_code_writer.booting_code())?;
+    if optimize {
+        output = optimize_assembly(&output).unwrap_or_else(|e| fail(e));
+    }
+    fs::write(&output_file_name, output)
+        .unwrap_or_else(|e| fail(io_error(Path::new(&output_file_name), e)));
+    if label_map {
+        let map_file_name = format!("{stem}.labels");
+        fs::write(&map_file_name, labels)
+            .unwrap_or_else(|e| fail(io_error(Path::new(&map_file_name), e)));
+    }
+}
 
-    for file in input_files {
-        let stem = file.file_stem().unwrap().to_str().unwrap();
-        let mut code_writer = CodeWriter::new(stem.to_string());
+// translate every file into one program named after `stem`, returning the
+// assembly and the label map
+fn translate(
+    files: &[(PathBuf, String)],
+    stem: &str,
+    compact: bool,
+    bootstrap: bool,
+) -> Result<(String, String), Vec<VmError>> {
+    let mut output = String::new();
 
-        let file = File::open(file)?;
-        let reader = BufReader::new(file);
+    // booting code
+    let mut boot_code_writer = CodeWriter::new(stem.to_string(), compact);
+    if bootstrap {
+        writeln!(output, "{}", boot_code_writer.booting_code()).unwrap();
+    } else {
+        writeln!(output, "{}", boot_code_writer.shared_routines()).unwrap();
+    }
 
-        for (_, line) in reader.lines().enumerate() {
-            let line = line?;
-            let line = line.trim();
-            if line.is_empty() || line.starts_with("//") {
-                continue;
-            }
+    // keep translating after an error so every bad line is reported in one run
+    let mut errors = vec![];
+    // `<asm label> <file>:<line> <vm label>` for every declared label
+    let mut labels = String::new();
+    for (file, source) in files {
+        let mut code_writer = CodeWriter::new(file_stem(file), compact);
 
-            let assembly = parse_line(&mut code_writer, line)?;
-            writeln!(output_file, "//{line}")?;
-            writeln!(output_file, "{assembly}\n")?;
+        for (line, text) in commands(source) {
+            match parse_line(&mut code_writer, text) {
+                Ok(assembly) => {
+                    writeln!(output, "//{text}").unwrap();
+                    writeln!(output, "{assembly}\n").unwrap();
+                    if let Some(label) = text.strip_prefix("label") {
+                        let label = label.trim();
+                        let mangled = code_writer.mangle(label);
+                        writeln!(labels, "{mangled} {}:{line} {label}", file.display()).unwrap();
+                    }
+                }
+                Err(error) => errors.push(VmError::Line {
+                    file: file.display().to_string(),
+                    line,
+                    error,
+                }),
+            }
         }
     }
+    errors.extend(validate::validate(files, bootstrap));
+    // merge syntax and validation errors by file and line, errors without a
+    // line come last
+    let names: Vec<String> = files
+        .iter()
+        .map(|(file, _)| file.display().to_string())
+        .collect();
+    errors.sort_by_key(|error| match error {
+        VmError::Line { file, line, .. } => (names.iter().position(|name| name == file), *line),
+        _ => (Some(usize::MAX), 0),
+    });
 
-    Ok(())
+    if errors.is_empty() {
+        Ok((output, labels))
+    } else {
+        Err(errors)
+    }
+}
+
+// the optimizer works on parsed instructions, so the VM comments are dropped
+fn optimize_assembly(assembly: &str) -> Result<String, VmError> {
+    // names the validator lets through can still be invalid Hack symbols
+    let instructions = assembler::parse(assembly).map_err(|errors| {
+        let reasons: Vec<String> = errors.iter().map(|error| error.kind.to_string()).collect();
+        VmError::InvalidAssembly(reasons.join(", "))
+    })?;
+    let (instructions, report) = assembler::optimize::optimize(&instructions);
+    eprintln!("{report}");
+    Ok(instructions
+        .iter()
+        .map(|instruction| format!("{instruction}\n"))
+        .collect())
+}
+
+// `out` for paths without a file name, like `/`
+fn file_stem(path: &Path) -> String {
+    path.file_stem().map_or("out".to_string(), |stem| {
+        stem.to_string_lossy().into_owned()
+    })
+}
+
+// the 1-based line number and text of every command, without comments
+fn commands(source: &str) -> impl Iterator<Item = (usize, &str)> {
+    source.lines().enumerate().filter_map(|(line_idx, line)| {
+        let text = line.find("//").map_or(line, |idx| &line[..idx]).trim();
+        (!text.is_empty()).then_some((line_idx + 1, text))
+    })
 }
 
 fn is_vm_file(path: &Path) -> bool {
-    path.extension().map_or(false, |ext| ext == "vm")
+    path.extension().is_some_and(|ext| ext == "vm")
 }
 
-fn get_input_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+fn get_input_files(path: &Path) -> std::io::Result<Vec<PathBuf>> {
     let mut files: Vec<PathBuf> = vec![];
 
     if path.is_file() {
@@ -73,47 +205,90 @@ fn get_input_files(path: &Path) -> io::Result<Vec<PathBuf>> {
     Ok(files)
 }
 
-fn parse_line(code_writer: &mut CodeWriter, line: &str) -> io::Result<String> {
+// the next argument of `command`, or an error naming what was expected
+fn argument<'a>(
+    parts: &mut impl Iterator<Item = &'a str>,
+    command: &str,
+    expected: &'static str,
+) -> Result<&'a str, LineError> {
+    parts.next().ok_or_else(|| LineError::MissingArgument {
+        command: command.to_string(),
+        expected,
+    })
+}
+
+fn number(value: &str) -> Result<u16, LineError> {
+    value
+        .parse()
+        .map_err(|_| LineError::InvalidNumber(value.to_string()))
+}
+
+fn parse_line(code_writer: &mut CodeWriter, line: &str) -> Result<String, LineError> {
     let mut parts = line.split_whitespace();
-    let command = parts.next().expect("emtpy line");
+    // callers skip blank lines
+    let command = parts.next().unwrap_or_default();
 
     let assembly = match command {
         // arithmetic/logical commands
         "add" | "sub" | "neg" | "eq" | "gt" | "lt" | "and" | "or" | "not" => {
-            code_writer.handle_arithmetic(command)
+            code_writer.handle_arithmetic(command)?
         }
         // memory access commands
         "push" | "pop" => {
-            let segment = parts.next().expect("Expected a segment after push/pop");
-            let index = parts
-                .next()
-                .expect("Expected an index after segment")
-                .parse::<u16>()
-                .expect("Failed to parse index");
-            code_writer.handle_memory_access(command, segment, index)
+            let segment = argument(&mut parts, command, "a segment")?;
+            let index = number(argument(&mut parts, command, "an index")?)?;
+            code_writer.handle_memory_access(command, segment, index)?
         }
         // Branching commands
         "label" | "goto" | "if-goto" => {
-            let label = parts.next().expect("Expected a label");
-            code_writer.handle_branching(command, label)
+            let label = argument(&mut parts, command, "a label")?;
+            code_writer.handle_branching(command, label)?
         }
         // Function commands
         "return" => code_writer.handle_return(),
         "function" | "call" => {
-            let name = parts.next().expect("Expected a function name");
-            let num_args = parts
-                .next()
-                .expect("Expected number of arguments for function")
-                .parse::<u16>()
-                .expect("Failed to parse number of arguments");
+            let name = argument(&mut parts, command, "a function name")?;
+            let expected = if command == "function" {
+                "a number of local variables"
+            } else {
+                "a number of arguments"
+            };
+            let num_args = number(argument(&mut parts, command, expected)?)?;
             if command == "function" {
                 code_writer.handle_function_init(name, num_args)
             } else {
                 code_writer.handle_function_call(name, num_args)
             }
         }
-        _ => panic!("unknown command: {command}"),
+        _ => return Err(LineError::UnknownCommand(command.to_string())),
     };
 
-    return Ok(assembly);
+    match parts.next() {
+        Some(extra) => Err(LineError::UnexpectedArgument(extra.to_string())),
+        None => Ok(assembly),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emulator::Cpu;
+
+    // eq, lt and gt at the 16-bit boundaries, where x - y overflows
+    #[test]
+    fn comparisons_at_overflow_boundaries() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples/CompareTest.vm");
+        let files = [(path.clone(), fs::read_to_string(&path).unwrap())];
+        for compact in [false, true] {
+            let (assembly, _) = translate(&files, "CompareTest", compact, true).unwrap();
+            for assembly in [optimize_assembly(&assembly).unwrap(), assembly] {
+                let words = assembler::assemble(&assembly).unwrap();
+                let mut cpu = Cpu::new(&words).unwrap();
+                cpu.run(10_000).unwrap();
+                // temp 0..7
+                let results: Vec<i16> = cpu.ram[5..13].iter().map(|word| *word as i16).collect();
+                assert_eq!(results, [0, -1, -1, -1, -1, 0, 0, -1]);
+            }
+        }
+    }
 }
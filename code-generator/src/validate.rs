@@ -0,0 +1,110 @@
+use crate::error::{LineError, VmError};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// largest constant an A-instruction can load, temp and pointer bounds are
+// checked by the translator as it computes their addresses
+const MAX_CONSTANT: u16 = 32767;
+
+// where a name is declared or used, as an index into the files and a 1-based line
+type Location = (usize, usize);
+
+// labels of the function being checked, labels before the first function
+// belong to a scope of their own
+#[derive(Default)]
+struct Scope<'a> {
+    function: Option<&'a str>,
+    labels: HashMap<&'a str, usize>,
+    jumps: Vec<(&'a str, Location)>,
+}
+
+impl<'a> Scope<'a> {
+    // report jumps to labels the function never declares
+    fn close(&mut self, errors: &mut Vec<(Location, LineError)>) {
+        for (label, location) in self.jumps.drain(..) {
+            if !self.labels.contains_key(label) {
+                let error = LineError::UndefinedLabel {
+                    label: label.to_string(),
+                    function: self.function.map(str::to_string),
+                };
+                errors.push((location, error));
+            }
+        }
+        self.labels.clear();
+    }
+}
+
+// check every file of a program before translating it, lines with syntax
+// errors are left to the translator
+pub fn validate(files: &[(PathBuf, String)], bootstrap: bool) -> Vec<VmError> {
+    let mut errors = vec![];
+    let mut functions: HashMap<&str, Location> = HashMap::new();
+    let mut calls = vec![];
+
+    for (file_idx, (_, source)) in files.iter().enumerate() {
+        let mut scope = Scope::default();
+        for (line, text) in crate::commands(source) {
+            let location = (file_idx, line);
+            let words: Vec<&str> = text.split_whitespace().collect();
+            match words.as_slice() {
+                ["pop", "constant", _] => errors.push((location, LineError::PopConstant)),
+                ["push", "constant", index] => {
+                    if let Some(index) = index.parse().ok().filter(|i| *i > MAX_CONSTANT) {
+                        let error = LineError::IndexOutOfRange {
+                            segment: "constant".to_string(),
+                            index,
+                            max: MAX_CONSTANT,
+                        };
+                        errors.push((location, error));
+                    }
+                }
+                ["label", label] => {
+                    if let Some(first) = scope.labels.insert(*label, line) {
+                        let error = LineError::DuplicateLabel {
+                            label: label.to_string(),
+                            first_line: first,
+                        };
+                        errors.push((location, error));
+                    }
+                }
+                ["goto" | "if-goto", label] => scope.jumps.push((*label, location)),
+                ["function", name, _] => {
+                    scope.close(&mut errors);
+                    scope.function = Some(*name);
+                    if let Some(&(first_file, first_line)) = functions.get(name) {
+                        let error = LineError::DuplicateFunction {
+                            name: name.to_string(),
+                            first: format!("{}:{first_line}", files[first_file].0.display()),
+                        };
+                        errors.push((location, error));
+                    } else {
+                        functions.insert(*name, location);
+                    }
+                }
+                ["call", name, _] => calls.push((*name, location)),
+                _ => {}
+            }
+        }
+        scope.close(&mut errors);
+    }
+
+    for (name, location) in calls {
+        if !functions.contains_key(name) {
+            errors.push((location, LineError::UndefinedFunction(name.to_string())));
+        }
+    }
+
+    errors.sort_by_key(|(location, _)| *location);
+    let mut errors: Vec<VmError> = errors
+        .into_iter()
+        .map(|((file_idx, line), error)| VmError::Line {
+            file: files[file_idx].0.display().to_string(),
+            line,
+            error,
+        })
+        .collect();
+    if bootstrap && !functions.contains_key("Sys.init") {
+        errors.push(VmError::MissingSysInit);
+    }
+    errors
+}
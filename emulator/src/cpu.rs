@@ -0,0 +1,179 @@
+use crate::error::EmulatorError;
+use assembler::ROM_SIZE;
+
+// memory map of the data memory
+pub const SCREEN: usize = 16384;
+pub const KBD: usize = 24576;
+pub const RAM_SIZE: usize = KBD + 1;
+
+// why `run` returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    // the program reached an `(END) @END 0;JMP` style infinite loop
+    Halted,
+    // the program counter left the loaded program
+    EndOfProgram,
+    CycleLimit,
+}
+
+// the Hack computer: CPU registers, instruction memory and data memory
+#[derive(Debug, Clone)]
+pub struct Cpu {
+    pub a: u16,
+    pub d: u16,
+    pub pc: u16,
+    pub rom: Vec<u16>,
+    pub ram: Vec<u16>,
+    // length of the loaded program, the rest of ROM is zero
+    pub program_len: usize,
+    // instructions executed since the last reset
+    pub cycles: u64,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Cpu {
+            a: 0,
+            d: 0,
+            pc: 0,
+            rom: vec![0; ROM_SIZE],
+            ram: vec![0; RAM_SIZE],
+            program_len: 0,
+            cycles: 0,
+        }
+    }
+}
+
+// the Hack ALU, `control` holds the zx nx zy ny f no bits
+fn alu(x: u16, y: u16, control: u16) -> u16 {
+    let bit = |n: u16| control & (1 << n) != 0;
+    let x = if bit(5) { 0 } else { x };
+    let x = if bit(4) { !x } else { x };
+    let y = if bit(3) { 0 } else { y };
+    let y = if bit(2) { !y } else { y };
+    let out = if bit(1) { x.wrapping_add(y) } else { x & y };
+    if bit(0) {
+        !out
+    } else {
+        out
+    }
+}
+
+impl Cpu {
+    pub fn new(program: &[u16]) -> Result<Self, EmulatorError> {
+        let mut cpu = Cpu::default();
+        cpu.load(program)?;
+        Ok(cpu)
+    }
+
+    // replace ROM with `program` and reset
+    pub fn load(&mut self, program: &[u16]) -> Result<(), EmulatorError> {
+        if program.len() > ROM_SIZE {
+            return Err(EmulatorError::ProgramTooLarge(program.len()));
+        }
+        self.rom.fill(0);
+        self.rom[..program.len()].copy_from_slice(program);
+        self.program_len = program.len();
+        self.reset();
+        Ok(())
+    }
+
+    // jump back to ROM[0], registers and RAM keep their values like the hardware reset pin
+    pub fn reset(&mut self) {
+        self.pc = 0;
+        self.cycles = 0;
+    }
+
+    // the keyboard register, 0 when no key is pressed
+    pub fn set_key(&mut self, key: u16) {
+        self.ram[KBD] = key;
+    }
+
+    fn check_address(&self, address: u16) -> Result<usize, EmulatorError> {
+        let address = address as usize;
+        if address < RAM_SIZE {
+            Ok(address)
+        } else {
+            Err(EmulatorError::InvalidAddress {
+                pc: self.pc,
+                address: address as u16,
+            })
+        }
+    }
+
+    // whether the next instructions are `@pc 0;JMP`, jumping back to themselves forever
+    pub fn is_halted(&self) -> bool {
+        let pc = self.pc as usize;
+        let jump = self.rom.get(pc + 1).copied().unwrap_or(0);
+        pc + 1 < self.program_len
+            && self.rom[pc] == self.pc
+            && jump & 0x8000 != 0
+            && jump & 0b111 == 0b111
+    }
+
+    // execute the instruction at PC
+    pub fn step(&mut self) -> Result<(), EmulatorError> {
+        let Some(&instruction) = self.rom.get(self.pc as usize) else {
+            return Err(EmulatorError::InvalidPc(self.pc));
+        };
+        if instruction & 0x8000 == 0 {
+            self.a = instruction;
+            self.pc = self.pc.wrapping_add(1) % ROM_SIZE as u16;
+            self.cycles += 1;
+            return Ok(());
+        }
+
+        let uses_m = instruction & 0x1000 != 0;
+        let y = if uses_m {
+            self.ram[self.check_address(self.a)?]
+        } else {
+            self.a
+        };
+        let out = alu(self.d, y, (instruction >> 6) & 0b11_1111);
+
+        // M and the jump target use A from before this instruction
+        let address = self.a;
+        if instruction & 0b1000 != 0 {
+            let address = self.check_address(address)?;
+            // the keyboard register is read-only
+            if address != KBD {
+                self.ram[address] = out;
+            }
+        }
+        if instruction & 0b10_0000 != 0 {
+            self.a = out;
+        }
+        if instruction & 0b1_0000 != 0 {
+            self.d = out;
+        }
+
+        let negative = out & 0x8000 != 0;
+        let jump = (instruction & 0b100 != 0 && negative)
+            || (instruction & 0b010 != 0 && out == 0)
+            || (instruction & 0b001 != 0 && !negative && out != 0);
+        self.pc = if jump {
+            address % ROM_SIZE as u16
+        } else {
+            self.pc.wrapping_add(1) % ROM_SIZE as u16
+        };
+        self.cycles += 1;
+        Ok(())
+    }
+
+    // step until the program halts, leaves ROM or `max_cycles` instructions have run
+    pub fn run(&mut self, max_cycles: u64) -> Result<Stop, EmulatorError> {
+        let limit = self.cycles.saturating_add(max_cycles);
+        loop {
+            if self.is_halted() {
+                return Ok(Stop::Halted);
+            }
+            if self.pc as usize >= self.program_len {
+                return Ok(Stop::EndOfProgram);
+            }
+            if self.cycles >= limit {
+                return Ok(Stop::CycleLimit);
+            }
+            self.step()?;
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use crate::cpu::Cpu;
+use crate::error::EmulatorError;
+use assembler::disassembler::disassemble_word;
+use assembler::{Program, SymbolKind, SymbolTable, ROM_SIZE};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+// `continue` and `until` give up after this many instructions
+const MAX_RUN_CYCLES: u64 = 100_000_000;
+
+const HELP: &str = "\
+step [n]            execute n instructions (s)
+continue            run to a breakpoint, watchpoint or halt (c)
+until <rom>         run until PC reaches an address or label (u)
+break <rom>         stop at an address or label (b)
+delete <rom>        remove a breakpoint (d)
+watch <ram>         stop when a RAM address or symbol changes (w)
+unwatch <ram>       remove a watchpoint
+info                list breakpoints and watchpoints
+regs                print A, D and PC (r)
+print <ram>[..<end>] print RAM with symbolic names (p)
+list [n]            disassemble n instructions from PC (l)
+set <reg|ram> <value> change A, D, PC or RAM
+reset               jump back to ROM[0]
+quit                leave the debugger (q)
+an empty line repeats the last command";
+
+// whether the debugger keeps reading commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    Quit,
+}
+
+// the CPU together with the program's symbols, breakpoints and watchpoints
+pub struct Debugger {
+    pub cpu: Cpu,
+    symbols: SymbolTable,
+    // ROM address of every label
+    labels: BTreeMap<u16, String>,
+    // names of named RAM addresses
+    ram_names: HashMap<u16, String>,
+    breakpoints: BTreeSet<u16>,
+    // watched RAM addresses with the value they had when last checked
+    watchpoints: BTreeMap<u16, u16>,
+}
+
+// the optional count argument of `line`, which must fit the type of `default`
+fn count<T: FromStr>(line: &str, arg: Option<&&str>, default: T) -> Result<T, EmulatorError> {
+    arg.map_or(Ok(default), |n| {
+        n.parse()
+            .map_err(|_| EmulatorError::InvalidCommand(line.trim().to_string()))
+    })
+}
+
+fn is_register_alias(name: &str) -> bool {
+    name.strip_prefix('R')
+        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+impl Debugger {
+    pub fn new(program: Program) -> Result<Self, EmulatorError> {
+        let cpu = Cpu::new(&program.words)?;
+        let labels = program
+            .symbols
+            .of_kind(SymbolKind::Label)
+            .into_iter()
+            .map(|(name, address)| (address, name.to_string()))
+            .collect();
+
+        // every name of a RAM address, e.g. `SP/R0`
+        let mut ram_names: HashMap<u16, String> = HashMap::new();
+        let mut named = program.symbols.of_kind(SymbolKind::Predefined);
+        named.extend(program.symbols.of_kind(SymbolKind::Variable));
+        named.sort_by_key(|(name, address)| (*address, is_register_alias(name)));
+        for (name, address) in named {
+            ram_names
+                .entry(address)
+                .and_modify(|names| *names += &format!("/{name}"))
+                .or_insert_with(|| name.to_string());
+        }
+
+        Ok(Debugger {
+            cpu,
+            symbols: program.symbols,
+            labels,
+            ram_names,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+        })
+    }
+
+    // `LOOP`, `LOOP+3` or the bare address when no label precedes it
+    fn rom_name(&self, address: u16) -> String {
+        match self.labels.range(..=address).next_back() {
+            Some((label_address, name)) if *label_address == address => name.clone(),
+            Some((label_address, name)) => format!("{name}+{}", address - label_address),
+            None => address.to_string(),
+        }
+    }
+
+    fn ram_name(&self, address: u16) -> String {
+        match self.ram_names.get(&address) {
+            Some(name) => format!("RAM[{address}] {name}"),
+            None => format!("RAM[{address}]"),
+        }
+    }
+
+    fn instruction(&self, address: u16) -> String {
+        let word = self.cpu.rom[address as usize];
+        let asm = disassemble_word(word).unwrap_or_else(|| format!("{word:016b}"));
+        if word & 0x8000 != 0 {
+            return asm;
+        }
+        // name the loaded address after a label when the next instruction jumps
+        let jumps = self
+            .cpu
+            .rom
+            .get(address as usize + 1)
+            .is_some_and(|next| next & 0x8000 != 0 && next & 0b111 != 0);
+        match (jumps, self.labels.get(&word), self.ram_names.get(&word)) {
+            (true, Some(label), _) => format!("{asm} ({label})"),
+            (false, _, Some(name)) => format!("{asm} ({name})"),
+            _ => asm,
+        }
+    }
+
+    fn location(&self) -> String {
+        let pc = self.cpu.pc;
+        format!(
+            "ROM[{pc}] <{}>: {}",
+            self.rom_name(pc),
+            self.instruction(pc)
+        )
+    }
+
+    fn resolve_rom(&self, text: &str) -> Result<u16, EmulatorError> {
+        text.parse()
+            .ok()
+            .or_else(|| {
+                self.symbols
+                    .symbol(text)
+                    .filter(|symbol| symbol.kind == SymbolKind::Label)
+                    .map(|symbol| symbol.address)
+            })
+            .ok_or_else(|| EmulatorError::UnknownSymbol(text.to_string()))
+    }
+
+    fn resolve_ram(&self, text: &str) -> Result<u16, EmulatorError> {
+        crate::resolve_ram(text, &self.symbols)
+    }
+
+    fn registers(&self) -> String {
+        let a = self.cpu.a;
+        let a_name = self
+            .ram_names
+            .get(&a)
+            .map_or(String::new(), |name| format!(" ({name})"));
+        format!(
+            "A={a}{a_name} D={} PC={} <{}> cycles={}",
+            self.cpu.d as i16,
+            self.cpu.pc,
+            self.rom_name(self.cpu.pc),
+            self.cpu.cycles
+        )
+    }
+
+    // step until a breakpoint, a changed watchpoint, `until`, a halt or the cycle limit
+    fn run(&mut self, max_cycles: u64, until: Option<u16>) -> Result<String, EmulatorError> {
+        for (address, value) in self.watchpoints.iter_mut() {
+            *value = self.cpu.ram[*address as usize];
+        }
+        for _ in 0..max_cycles {
+            if self.cpu.is_halted() {
+                return Ok(format!("program halted\n{}", self.location()));
+            }
+            self.cpu.step()?;
+
+            let mut changes = vec![];
+            for (address, value) in self.watchpoints.iter_mut() {
+                let new = self.cpu.ram[*address as usize];
+                if new != *value {
+                    changes.push((*address, *value, new));
+                    *value = new;
+                }
+            }
+            if !changes.is_empty() {
+                let mut out = String::new();
+                for (address, old, new) in changes {
+                    let name = self.ram_name(address);
+                    out += &format!("watchpoint {name}: {} -> {}\n", old as i16, new as i16);
+                }
+                return Ok(out + &self.location());
+            }
+            if until == Some(self.cpu.pc) {
+                return Ok(self.location());
+            }
+            if self.breakpoints.contains(&self.cpu.pc) {
+                return Ok(format!("breakpoint\n{}", self.location()));
+            }
+        }
+        Ok(self.location())
+    }
+
+    fn print(&self, range: &str) -> Result<String, EmulatorError> {
+        let (start, end) = range.split_once("..").unwrap_or((range, range));
+        let (start, end) = (self.resolve_ram(start)?, self.resolve_ram(end)?);
+        let lines: Vec<String> = (start..=end)
+            .map(|address| {
+                let value = self.cpu.ram[address as usize];
+                format!("{} = {}", self.ram_name(address), value as i16)
+            })
+            .collect();
+        Ok(lines.join("\n"))
+    }
+
+    fn list(&self, count: u16) -> String {
+        let mut lines = vec![];
+        let start = self.cpu.pc;
+        for address in start..start.saturating_add(count) {
+            if address as usize >= self.cpu.program_len {
+                break;
+            }
+            if let Some(label) = self.labels.get(&address) {
+                lines.push(format!("({label})"));
+            }
+            let marker = if address == self.cpu.pc { "=>" } else { "  " };
+            let breakpoint = if self.breakpoints.contains(&address) {
+                "*"
+            } else {
+                " "
+            };
+            lines.push(format!(
+                "{marker}{breakpoint}{address:<6}{}",
+                self.instruction(address)
+            ));
+        }
+        lines.join("\n")
+    }
+
+    fn info(&self) -> String {
+        let mut lines = vec![];
+        for address in &self.breakpoints {
+            lines.push(format!(
+                "breakpoint ROM[{address}] <{}>",
+                self.rom_name(*address)
+            ));
+        }
+        for address in self.watchpoints.keys() {
+            lines.push(format!("watchpoint {}", self.ram_name(*address)));
+        }
+        if lines.is_empty() {
+            lines.push("no breakpoints or watchpoints".to_string());
+        }
+        lines.join("\n")
+    }
+
+    fn set(&mut self, target: &str, value: &str) -> Result<String, EmulatorError> {
+        let value = value
+            .parse::<i16>()
+            .map_err(|_| EmulatorError::InvalidCommand(format!("invalid value `{value}`")))?
+            as u16;
+        match target {
+            "A" => self.cpu.a = value,
+            "D" => self.cpu.d = value,
+            "PC" if value as usize >= ROM_SIZE => return Err(EmulatorError::InvalidPc(value)),
+            "PC" => self.cpu.pc = value,
+            _ => {
+                let address = self.resolve_ram(target)?;
+                self.cpu.ram[address as usize] = value;
+                return self.print(target);
+            }
+        }
+        Ok(self.registers())
+    }
+
+    // run one command line, returning what to print
+    pub fn execute(&mut self, line: &str) -> Result<(String, Action), EmulatorError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let out = match words.as_slice() {
+            ["step" | "s", rest @ ..] if rest.len() <= 1 => {
+                let steps = count(line, rest.first(), 1u64)?;
+                self.run(steps, None)?
+            }
+            ["continue" | "c"] => self.run(MAX_RUN_CYCLES, None)?,
+            ["until" | "u", target] => {
+                let target = self.resolve_rom(target)?;
+                self.run(MAX_RUN_CYCLES, Some(target))?
+            }
+            ["break" | "b", target] => {
+                let address = self.resolve_rom(target)?;
+                self.breakpoints.insert(address);
+                format!("breakpoint at ROM[{address}] <{}>", self.rom_name(address))
+            }
+            ["delete" | "d", target] => {
+                let address = self.resolve_rom(target)?;
+                if self.breakpoints.remove(&address) {
+                    format!("deleted breakpoint at ROM[{address}]")
+                } else {
+                    format!("no breakpoint at ROM[{address}]")
+                }
+            }
+            ["watch" | "w", target] => {
+                let address = self.resolve_ram(target)?;
+                self.watchpoints
+                    .insert(address, self.cpu.ram[address as usize]);
+                format!("watching {}", self.ram_name(address))
+            }
+            ["unwatch", target] => {
+                let address = self.resolve_ram(target)?;
+                if self.watchpoints.remove(&address).is_some() {
+                    format!("stopped watching {}", self.ram_name(address))
+                } else {
+                    format!("{} is not watched", self.ram_name(address))
+                }
+            }
+            ["info"] => self.info(),
+            ["regs" | "r"] => self.registers(),
+            ["print" | "p", range] => self.print(range)?,
+            ["list" | "l", rest @ ..] if rest.len() <= 1 => {
+                let lines = count(line, rest.first(), 10u16)?;
+                self.list(lines)
+            }
+            ["set", target, value] => self.set(target, value)?,
+            ["reset"] => {
+                self.cpu.reset();
+                self.location()
+            }
+            ["help" | "h"] => HELP.to_string(),
+            ["quit" | "q"] => return Ok((String::new(), Action::Quit)),
+            _ => return Err(EmulatorError::InvalidCommand(line.trim().to_string())),
+        };
+        Ok((out, Action::Continue))
+    }
+}
+
+// read commands until `quit` or the end of input
+pub fn repl(
+    debugger: &mut Debugger,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<()> {
+    writeln!(output, "{}", debugger.location())?;
+    let mut last = String::new();
+    let mut lines = input.lines();
+    loop {
+        write!(output, "(hdb) ")?;
+        output.flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            break;
+        };
+        let line = if line.trim().is_empty() {
+            last.clone()
+        } else {
+            line
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match debugger.execute(&line) {
+            Ok((_, Action::Quit)) => break,
+            Ok((out, Action::Continue)) => writeln!(output, "{out}")?,
+            Err(e) => writeln!(output, "error: {e}")?,
+        }
+        last = line;
+    }
+    Ok(())
+}
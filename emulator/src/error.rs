@@ -0,0 +1,57 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmulatorError {
+    Io { path: String, reason: String },
+    // rendered assembler or .hack parse diagnostics
+    InvalidProgram(String),
+    ProgramTooLarge(usize),
+    // an instruction read or wrote M outside of RAM, SCREEN and KBD
+    InvalidAddress { pc: u16, address: u16 },
+    // PC was set past the end of ROM
+    InvalidPc(u16),
+    UnknownSymbol(String),
+    // a RAM address past the keyboard register
+    RamOutOfRange(String),
+    InvalidImage(String),
+    InvalidKeyScript { line: usize, reason: String },
+    InvalidScript { line: usize, reason: String },
+    InvalidCommand(String),
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulatorError::Io { path, reason } => write!(f, "could not read `{path}`: {reason}"),
+            EmulatorError::InvalidProgram(diagnostics) => write!(f, "{diagnostics}"),
+            EmulatorError::ProgramTooLarge(size) => {
+                write!(f, "program has {size} instructions, ROM only holds 32768")
+            }
+            EmulatorError::InvalidAddress { pc, address } => write!(
+                f,
+                "instruction at ROM[{pc}] accesses RAM[{address}], past the keyboard register"
+            ),
+            EmulatorError::InvalidPc(pc) => {
+                write!(f, "PC {pc} is past the end of ROM, which holds 32768 words")
+            }
+            EmulatorError::UnknownSymbol(name) => {
+                write!(f, "`{name}` is not an address or a symbol of the program")
+            }
+            EmulatorError::RamOutOfRange(address) => {
+                write!(f, "`{address}` is past the keyboard register at 24576")
+            }
+            EmulatorError::InvalidImage(reason) => write!(f, "invalid golden image: {reason}"),
+            EmulatorError::InvalidKeyScript { line, reason } => {
+                write!(f, "invalid keystroke script line {line}: {reason}")
+            }
+            EmulatorError::InvalidScript { line, reason } => {
+                write!(f, "invalid test script line {line}: {reason}")
+            }
+            EmulatorError::InvalidCommand(command) => {
+                write!(f, "invalid command `{command}`, try `help`")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
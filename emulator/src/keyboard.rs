@@ -0,0 +1,155 @@
+use crate::cpu::{Cpu, Stop};
+use crate::error::EmulatorError;
+
+// Hack codes of the keys that have no character
+const SPECIAL_KEYS: [(&str, u16); 13] = [
+    ("newline", 128),
+    ("backspace", 129),
+    ("left", 130),
+    ("up", 131),
+    ("right", 132),
+    ("down", 133),
+    ("home", 134),
+    ("end", 135),
+    ("pageup", 136),
+    ("pagedown", 137),
+    ("insert", 138),
+    ("delete", 139),
+    ("esc", 140),
+];
+
+// cycles each character of a typed string is held down and then released for
+const DEFAULT_TYPING_GAP: u64 = 50_000;
+
+// the keyboard register holds `key` from `cycle` on, 0 meaning no key is pressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub cycle: u64,
+    pub key: u16,
+}
+
+// keystrokes fed to the KBD register at fixed cycles
+#[derive(Debug, Clone, Default)]
+pub struct KeyScript {
+    pub events: Vec<KeyEvent>,
+    next: usize,
+}
+
+// Hack code of a key name, a quoted character or a number
+fn key_code(key: &str) -> Option<u16> {
+    if let Some((_, code)) = SPECIAL_KEYS.iter().find(|(name, _)| *name == key) {
+        return Some(*code);
+    }
+    if let Some(f) = key.strip_prefix('f').and_then(|n| n.parse::<u16>().ok()) {
+        return (1..=12).contains(&f).then_some(140 + f);
+    }
+    if key == "release" {
+        return Some(0);
+    }
+    let quoted = key
+        .strip_prefix('\'')
+        .and_then(|key| key.strip_suffix('\''));
+    match quoted.map(|c| c.chars().collect::<Vec<_>>()).as_deref() {
+        Some([c]) if c.is_ascii() => Some(*c as u16),
+        Some(_) => None,
+        None => key.parse().ok(),
+    }
+}
+
+// Hack codes of the characters of a typed string, `\n` being the newline key
+fn typed_codes(text: &str) -> Option<Vec<u16>> {
+    let mut codes = vec![];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        let code = match c {
+            '\\' => match chars.next()? {
+                'n' => 128,
+                '\\' => '\\' as u16,
+                '"' => '"' as u16,
+                _ => return None,
+            },
+            c if c.is_ascii() && !c.is_ascii_control() => c as u16,
+            _ => return None,
+        };
+        codes.push(code);
+    }
+    Some(codes)
+}
+
+impl KeyScript {
+    // one event per line: `<cycle> <key>` where the key is a number, a quoted
+    // character, a special key name, `f1`..`f12` or `release`; `<cycle> "text" [gap]`
+    // types a string, pressing and releasing each key for `gap` cycles
+    pub fn parse(source: &str) -> Result<Self, EmulatorError> {
+        let mut events = vec![];
+        for (line_idx, raw_line) in source.lines().enumerate() {
+            let error = |reason: &str| EmulatorError::InvalidKeyScript {
+                line: line_idx + 1,
+                reason: reason.to_string(),
+            };
+            let line = raw_line.find("//").map_or(raw_line, |idx| &raw_line[..idx]);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (cycle, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let cycle: u64 = cycle
+                .parse()
+                .map_err(|_| error("expected a cycle number"))?;
+            let rest = rest.trim();
+
+            if let Some(text) = rest.strip_prefix('"') {
+                let (text, gap) = text
+                    .rsplit_once('"')
+                    .ok_or_else(|| error("unterminated string"))?;
+                let gap = match gap.trim() {
+                    "" => DEFAULT_TYPING_GAP,
+                    gap => gap.parse().map_err(|_| error("expected a cycle gap"))?,
+                };
+                let codes = typed_codes(text).ok_or_else(|| error("unsupported character"))?;
+                for (idx, key) in codes.into_iter().enumerate() {
+                    let press = cycle + 2 * idx as u64 * gap;
+                    events.push(KeyEvent { cycle: press, key });
+                    events.push(KeyEvent {
+                        cycle: press + gap,
+                        key: 0,
+                    });
+                }
+            } else {
+                let key = key_code(rest).ok_or_else(|| error("unknown key"))?;
+                events.push(KeyEvent { cycle, key });
+            }
+        }
+        // later lines win for the same cycle
+        events.sort_by_key(|event| event.cycle);
+        Ok(KeyScript { events, next: 0 })
+    }
+
+    // set KBD to the latest event that is due
+    fn apply(&mut self, cpu: &mut Cpu) {
+        while let Some(event) = self.events.get(self.next) {
+            if event.cycle > cpu.cycles {
+                break;
+            }
+            cpu.set_key(event.key);
+            self.next += 1;
+        }
+    }
+
+    // like `Cpu::run`, pressing keys as their cycles come up
+    pub fn run(&mut self, cpu: &mut Cpu, max_cycles: u64) -> Result<Stop, EmulatorError> {
+        let limit = cpu.cycles.saturating_add(max_cycles);
+        loop {
+            self.apply(cpu);
+            let until = self
+                .events
+                .get(self.next)
+                .map_or(limit, |event| event.cycle.min(limit));
+            let stop = cpu.run(until - cpu.cycles)?;
+            if stop != Stop::CycleLimit || cpu.cycles >= limit {
+                return Ok(stop);
+            }
+        }
+    }
+}
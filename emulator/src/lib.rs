@@ -0,0 +1,60 @@
+use assembler::source::SourceMap;
+use assembler::{Program, SymbolTable};
+use error::EmulatorError;
+use std::path::Path;
+
+pub mod cpu;
+pub mod debugger;
+pub mod error;
+pub mod keyboard;
+pub mod screen;
+pub mod test_script;
+
+pub use cpu::{Cpu, Stop};
+
+// machine code from a `.hack` file, or from a `.asm` file through the assembler
+// together with its symbols
+pub fn load_program(path: &Path) -> Result<Program, EmulatorError> {
+    let name = path.display().to_string();
+    let source = std::fs::read_to_string(path).map_err(|e| EmulatorError::Io {
+        path: name.clone(),
+        reason: e.to_string(),
+    })?;
+    let mut sources = SourceMap::default();
+    sources.add(&name, Some(path.to_path_buf()), source.clone(), None);
+
+    let render = |sources: &SourceMap, errors: &[assembler::AsmError]| {
+        let diagnostics: Vec<String> = errors.iter().map(|e| sources.render(e)).collect();
+        EmulatorError::InvalidProgram(diagnostics.join("\n"))
+    };
+    if path.extension().is_some_and(|ext| ext == "asm") {
+        let instructions =
+            assembler::parser::parse_with(&mut sources, &[]).map_err(|e| render(&sources, &e))?;
+        assembler::assemble_instructions(&instructions).map_err(|e| render(&sources, &e))
+    } else {
+        let words =
+            assembler::disassembler::parse_hack(&source).map_err(|e| render(&sources, &e))?;
+        Ok(Program {
+            words,
+            symbols: SymbolTable::new(),
+        })
+    }
+}
+
+// a RAM or ROM address written as a number or a symbol of the program
+pub fn resolve_address(text: &str, symbols: &SymbolTable) -> Result<u16, EmulatorError> {
+    text.parse()
+        .ok()
+        .or_else(|| symbols.get(text))
+        .ok_or_else(|| EmulatorError::UnknownSymbol(text.to_string()))
+}
+
+// like `resolve_address`, for addresses that index RAM
+pub fn resolve_ram(text: &str, symbols: &SymbolTable) -> Result<u16, EmulatorError> {
+    let address = resolve_address(text, symbols)?;
+    if (address as usize) < cpu::RAM_SIZE {
+        Ok(address)
+    } else {
+        Err(EmulatorError::RamOutOfRange(text.to_string()))
+    }
+}
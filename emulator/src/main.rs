@@ -0,0 +1,204 @@
+use emulator::debugger::{repl, Debugger};
+use emulator::error::EmulatorError;
+use emulator::keyboard::KeyScript;
+use emulator::screen::Screen;
+use emulator::test_script::run_test;
+use emulator::{load_program, resolve_ram, Cpu, Stop};
+use std::io;
+use std::path::Path;
+
+// default cycle limit, enough for most course programs
+const MAX_CYCLES: u64 = 10_000_000;
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {program} test <file.tst>...\n       {program} debug <file.hack|file.asm>\n       {program} [--cycles <n>] [--set <addr>=<value>]... [--ram <addr>[..<end>]]... [--keys <script>]\n       [--screen <file.ppm|file.png>] [--screen-at <cycle>] [--compare <golden.ppm>] <file.hack|file.asm>"
+    );
+    std::process::exit(1);
+}
+
+fn fail(error: EmulatorError) -> ! {
+    eprintln!("error: {error}");
+    std::process::exit(1);
+}
+
+fn write_file(path: &str, content: &[u8]) {
+    std::fs::write(path, content).unwrap_or_else(|e| {
+        fail(EmulatorError::Io {
+            path: path.to_string(),
+            reason: e.to_string(),
+        })
+    });
+}
+
+// compare the screen with a golden image, returning whether they match
+fn compare_screen(screen: &Screen, golden: &str) -> bool {
+    let bytes = std::fs::read(golden).unwrap_or_else(|e| {
+        fail(EmulatorError::Io {
+            path: golden.to_string(),
+            reason: e.to_string(),
+        })
+    });
+    let expected = Screen::from_ppm(&bytes).unwrap_or_else(|e| fail(e));
+    match screen.diff(&expected) {
+        None => {
+            println!("screen matches `{golden}`");
+            true
+        }
+        Some(diff) => {
+            let (left, top, right, bottom) = diff.bounds;
+            println!(
+                "screen differs from `{golden}` in {} pixel(s) between ({left}, {top}) and ({right}, {bottom})",
+                diff.count
+            );
+            false
+        }
+    }
+}
+
+// run CPU emulator test scripts, returning whether all of them passed
+fn run_tests(scripts: &[String]) -> bool {
+    let mut passed = true;
+    for script in scripts {
+        let outcome = match run_test(Path::new(script)) {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                eprintln!("error: {script}: {e}");
+                passed = false;
+                continue;
+            }
+        };
+        for echo in &outcome.echo {
+            println!("{script}: {echo}");
+        }
+        match outcome.comparison {
+            None => println!("{script}: end of script"),
+            Some(Ok(())) => println!("{script}: comparison ended successfully"),
+            Some(Err(mismatch)) => {
+                println!("{script}: comparison failure at line {}", mismatch.line);
+                println!("  expected: {}", mismatch.expected);
+                println!("  actual:   {}", mismatch.actual);
+                passed = false;
+            }
+        }
+    }
+    passed
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|arg| arg == "test") {
+        if args.len() < 3 {
+            usage(&args[0]);
+        }
+        if !run_tests(&args[2..]) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let [_, command, file] = args.as_slice() {
+        if command == "debug" {
+            let program = load_program(Path::new(file)).unwrap_or_else(|e| fail(e));
+            let mut debugger = Debugger::new(program).unwrap_or_else(|e| fail(e));
+            if let Err(e) = repl(&mut debugger, io::stdin().lock(), io::stdout()) {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
+    let mut max_cycles = MAX_CYCLES;
+    let mut sets = vec![];
+    let mut ranges = vec![];
+    let mut screen_path = None;
+    let mut screen_at = None;
+    let mut golden = None;
+    let mut keys = KeyScript::default();
+    let mut file = None;
+    let mut args_iter = args[1..].iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--cycles" | "-n" => {
+                let cycles = args_iter.next().unwrap_or_else(|| usage(&args[0]));
+                max_cycles = cycles.parse().unwrap_or_else(|_| usage(&args[0]));
+            }
+            "--set" => {
+                let set = args_iter.next().unwrap_or_else(|| usage(&args[0]));
+                let (address, value) = set.split_once('=').unwrap_or_else(|| usage(&args[0]));
+                let value: i16 = value.parse().unwrap_or_else(|_| usage(&args[0]));
+                sets.push((address, value as u16));
+            }
+            "--ram" => {
+                let range = args_iter.next().unwrap_or_else(|| usage(&args[0]));
+                ranges.push(range.as_str());
+            }
+            "--screen" => {
+                let path = args_iter.next().unwrap_or_else(|| usage(&args[0]));
+                screen_path = Some(path.as_str());
+            }
+            "--screen-at" => {
+                let cycle = args_iter.next().unwrap_or_else(|| usage(&args[0]));
+                screen_at = Some(cycle.parse().unwrap_or_else(|_| usage(&args[0])));
+            }
+            "--compare" => {
+                let path = args_iter.next().unwrap_or_else(|| usage(&args[0]));
+                golden = Some(path.as_str());
+            }
+            "--keys" => {
+                let path = args_iter.next().unwrap_or_else(|| usage(&args[0]));
+                let script = std::fs::read_to_string(path).unwrap_or_else(|e| {
+                    fail(EmulatorError::Io {
+                        path: path.clone(),
+                        reason: e.to_string(),
+                    })
+                });
+                keys = KeyScript::parse(&script).unwrap_or_else(|e| fail(e));
+            }
+            _ if file.is_none() => file = Some(arg.as_str()),
+            _ => usage(&args[0]),
+        }
+    }
+    let file = file.unwrap_or_else(|| usage(&args[0]));
+
+    let program = load_program(Path::new(file)).unwrap_or_else(|e| fail(e));
+    let mut cpu = Cpu::new(&program.words).unwrap_or_else(|e| fail(e));
+    for (address, value) in sets {
+        let address = resolve_ram(address, &program.symbols).unwrap_or_else(|e| fail(e));
+        cpu.ram[address as usize] = value;
+    }
+
+    // the screen is captured at `--screen-at` if the program gets that far, else when it stops
+    let mut screen = None;
+    let mut stop = keys.run(&mut cpu, max_cycles.min(screen_at.unwrap_or(max_cycles)));
+    if let (Some(at), Ok(Stop::CycleLimit)) = (screen_at, &stop) {
+        screen = Some(Screen::from_ram(&cpu.ram));
+        stop = keys.run(&mut cpu, max_cycles.saturating_sub(at));
+    }
+    let stop = stop.unwrap_or_else(|e| fail(e));
+    let screen = screen.unwrap_or_else(|| Screen::from_ram(&cpu.ram));
+    let reason = match stop {
+        Stop::Halted => "halted",
+        Stop::EndOfProgram => "ran past the end of the program",
+        Stop::CycleLimit => "reached the cycle limit",
+    };
+    println!("{reason} after {} cycles", cpu.cycles);
+    println!("PC={} A={} D={}", cpu.pc, cpu.a, cpu.d as i16);
+
+    for range in ranges {
+        let (start, end) = range.split_once("..").unwrap_or((range, range));
+        let start = resolve_ram(start, &program.symbols).unwrap_or_else(|e| fail(e));
+        let end = resolve_ram(end, &program.symbols).unwrap_or_else(|e| fail(e));
+        for address in start..=end {
+            println!("RAM[{address}] = {}", cpu.ram[address as usize] as i16);
+        }
+    }
+
+    if let Some(path) = screen_path {
+        write_file(path, &screen.encode_for(path));
+    }
+    if let Some(golden) = golden {
+        if !compare_screen(&screen, golden) {
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use crate::cpu::SCREEN;
+use crate::error::EmulatorError;
+
+pub const WIDTH: usize = 512;
+pub const HEIGHT: usize = 256;
+
+// words of SCREEN memory per row, bit 0 of a word is its leftmost pixel
+const ROW_WORDS: usize = WIDTH / 16;
+
+// a snapshot of the SCREEN memory map, true for black pixels
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screen {
+    pub pixels: Vec<bool>,
+}
+
+// pixels that differ between two screens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenDiff {
+    pub count: usize,
+    // smallest rectangle holding every difference, as (left, top, right, bottom)
+    pub bounds: (usize, usize, usize, usize),
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+// zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut chunks = data.chunks(u16::MAX as usize).peekable();
+    while let Some(chunk) = chunks.next() {
+        out.push(chunks.peek().is_none() as u8);
+        let len = chunk.len() as u16;
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(chunk);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend(kind);
+    out.extend(data);
+    let crc = crc32(&out[start..]);
+    out.extend(crc.to_be_bytes());
+}
+
+impl Screen {
+    pub fn from_ram(ram: &[u16]) -> Self {
+        let pixels = (0..WIDTH * HEIGHT)
+            .map(|idx| {
+                let (row, column) = (idx / WIDTH, idx % WIDTH);
+                let word = ram[SCREEN + row * ROW_WORDS + column / 16];
+                word & (1 << (column % 16)) != 0
+            })
+            .collect();
+        Screen { pixels }
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * WIDTH + x]
+    }
+
+    // binary PPM, black on white
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut out = format!("P6\n{WIDTH} {HEIGHT}\n255\n").into_bytes();
+        for &black in &self.pixels {
+            let value = if black { 0 } else { 255 };
+            out.extend([value; 3]);
+        }
+        out
+    }
+
+    // 1-bit grayscale PNG, stored without compression
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(HEIGHT * (WIDTH / 8 + 1));
+        for y in 0..HEIGHT {
+            raw.push(0); // no filter
+            for x in (0..WIDTH).step_by(8) {
+                // grayscale 1 is white, so black pixels are cleared bits
+                let byte = (0..8).fold(0u8, |byte, bit| {
+                    byte | ((!self.pixel(x + bit, y) as u8) << (7 - bit))
+                });
+                raw.push(byte);
+            }
+        }
+
+        let mut header = vec![];
+        header.extend((WIDTH as u32).to_be_bytes());
+        header.extend((HEIGHT as u32).to_be_bytes());
+        header.extend([1, 0, 0, 0, 0]); // bit depth, grayscale, deflate, no filter, no interlace
+
+        let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut out, b"IHDR", &header);
+        png_chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    // PNG when `path` ends in .png, PPM otherwise
+    pub fn encode_for(&self, path: &str) -> Vec<u8> {
+        if path.ends_with(".png") {
+            self.to_png()
+        } else {
+            self.to_ppm()
+        }
+    }
+
+    // read a 512x256 binary (P6) or plain (P3) PPM, any non-white pixel counts as black
+    pub fn from_ppm(bytes: &[u8]) -> Result<Self, EmulatorError> {
+        let invalid = |reason: &str| EmulatorError::InvalidImage(reason.to_string());
+
+        // header fields are whitespace separated, with `#` comments
+        let mut fields = vec![];
+        let mut idx = 0;
+        while fields.len() < 4 {
+            while idx < bytes.len() && bytes[idx].is_ascii_whitespace() {
+                idx += 1;
+            }
+            if bytes.get(idx) == Some(&b'#') {
+                while idx < bytes.len() && bytes[idx] != b'\n' {
+                    idx += 1;
+                }
+                continue;
+            }
+            let start = idx;
+            while idx < bytes.len() && !bytes[idx].is_ascii_whitespace() {
+                idx += 1;
+            }
+            if start == idx {
+                return Err(invalid("truncated PPM header"));
+            }
+            fields.push(String::from_utf8_lossy(&bytes[start..idx]).into_owned());
+        }
+        let size = (fields[1].parse(), fields[2].parse());
+        if size != (Ok(WIDTH), Ok(HEIGHT)) {
+            return Err(invalid("image is not 512x256"));
+        }
+        let max: u16 = fields[3]
+            .parse()
+            .map_err(|_| invalid("invalid PPM maximum"))?;
+
+        let samples: Vec<u16> = match fields[0].as_str() {
+            "P6" if max < 256 => bytes
+                .get(idx + 1..)
+                .unwrap_or(&[])
+                .iter()
+                .map(|b| *b as u16)
+                .collect(),
+            "P3" => String::from_utf8_lossy(&bytes[idx..])
+                .split_whitespace()
+                .map(|sample| sample.parse().map_err(|_| invalid("invalid PPM sample")))
+                .collect::<Result<_, _>>()?,
+            _ => return Err(invalid("only 8-bit P6 and P3 PPM images are supported")),
+        };
+        if samples.len() < WIDTH * HEIGHT * 3 {
+            return Err(invalid("PPM image is truncated"));
+        }
+        let pixels = samples
+            .chunks(3)
+            .take(WIDTH * HEIGHT)
+            .map(|rgb| rgb.iter().any(|sample| *sample < max))
+            .collect();
+        Ok(Screen { pixels })
+    }
+
+    pub fn diff(&self, expected: &Screen) -> Option<ScreenDiff> {
+        let mut diff: Option<ScreenDiff> = None;
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                if self.pixel(x, y) == expected.pixel(x, y) {
+                    continue;
+                }
+                let diff = diff.get_or_insert(ScreenDiff {
+                    count: 0,
+                    bounds: (x, y, x, y),
+                });
+                let (left, top, right, bottom) = diff.bounds;
+                diff.count += 1;
+                diff.bounds = (left.min(x), top.min(y), right.max(x), bottom.max(y));
+            }
+        }
+        diff
+    }
+}
@@ -0,0 +1,573 @@
+use crate::cpu::{Cpu, RAM_SIZE};
+use crate::error::EmulatorError;
+use crate::load_program;
+use assembler::ROM_SIZE;
+use std::path::{Path, PathBuf};
+
+// bound for `repeat { ... }` without a count when the program never halts
+const MAX_REPEAT: u64 = 10_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    A,
+    D,
+    Pc,
+    Ram(u16),
+    Rom(u16),
+    Time,
+}
+
+// an `output-list` column such as `RAM[0]%D2.6.2`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Column {
+    name: String,
+    variable: Variable,
+    format: char,
+    left: usize,
+    len: usize,
+    right: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    Load(Option<String>),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<Column>),
+    Set(Variable, u16),
+    Tick,
+    Tock,
+    TickTock,
+    Output,
+    Echo(String),
+    ClearEcho,
+    Repeat(Option<u64>, Vec<Command>),
+    While(Variable, Comparison, u16, Vec<Command>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Open,
+    Close,
+    // `,` `;` and `!` all end a command when running unattended
+    End,
+}
+
+fn tokenize(source: &str) -> Vec<(usize, Token)> {
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => while chars.next_if(|c| *c != '\n').is_some() {},
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            }
+            '"' => {
+                let mut text = String::new();
+                for c in chars.by_ref() {
+                    if c == '"' {
+                        break;
+                    }
+                    text.push(c);
+                }
+                tokens.push((line, Token::Text(text)));
+            }
+            '{' => tokens.push((line, Token::Open)),
+            '}' => tokens.push((line, Token::Close)),
+            ',' | ';' | '!' => tokens.push((line, Token::End)),
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|c| {
+                    !c.is_whitespace() && !matches!(c, ',' | ';' | '!' | '{' | '}' | '"')
+                }) {
+                    word.push(c);
+                }
+                tokens.push((line, Token::Word(word)));
+            }
+        }
+    }
+    tokens
+}
+
+fn parse_variable(name: &str) -> Option<Variable> {
+    let indexed = |prefix: &str, size: usize| {
+        name.strip_prefix(prefix)?
+            .strip_suffix(']')?
+            .parse::<u16>()
+            .ok()
+            .filter(|idx| (*idx as usize) < size)
+    };
+    match name {
+        "A" => Some(Variable::A),
+        "D" => Some(Variable::D),
+        "PC" => Some(Variable::Pc),
+        "time" => Some(Variable::Time),
+        _ => indexed("RAM[", RAM_SIZE)
+            .map(Variable::Ram)
+            .or_else(|| indexed("ROM[", ROM_SIZE).map(Variable::Rom)),
+    }
+}
+
+// `-5`, `%D-5`, `%X1F` or `%B101`
+fn parse_value(text: &str) -> Option<u16> {
+    let (digits, radix) = match text.get(..2) {
+        Some("%X") => (&text[2..], 16),
+        Some("%B") => (&text[2..], 2),
+        Some("%D") => (&text[2..], 10),
+        _ => (text, 10),
+    };
+    if radix == 10 {
+        digits.parse::<i16>().ok().map(|value| value as u16)
+    } else {
+        u16::from_str_radix(digits, radix).ok()
+    }
+}
+
+// `name%F<left>.<len>.<right>`, the format defaulting to `%D1.6.1`
+fn parse_column(text: &str) -> Option<Column> {
+    let (name, format) = text.split_once('%').unwrap_or((text, "D1.6.1"));
+    let variable = parse_variable(name)?;
+    let mut chars = format.chars();
+    let kind = chars
+        .next()
+        .filter(|c| matches!(c, 'D' | 'X' | 'B' | 'S'))?;
+    let widths: Vec<usize> = chars
+        .as_str()
+        .split('.')
+        .map(|width| width.parse().ok())
+        .collect::<Option<_>>()?;
+    let [left, len, right] = widths[..] else {
+        return None;
+    };
+    Some(Column {
+        name: name.to_string(),
+        variable,
+        format: kind,
+        left,
+        len,
+        right,
+    })
+}
+
+fn parse_comparison(text: &str) -> Option<Comparison> {
+    match text {
+        "=" => Some(Comparison::Eq),
+        "<>" => Some(Comparison::Ne),
+        "<" => Some(Comparison::Lt),
+        ">" => Some(Comparison::Gt),
+        "<=" => Some(Comparison::Le),
+        ">=" => Some(Comparison::Ge),
+        _ => None,
+    }
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    idx: usize,
+}
+
+impl Parser {
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.idx.min(self.tokens.len().saturating_sub(1)))
+            .map_or(1, |(line, _)| *line)
+    }
+
+    fn error(&self, reason: impl Into<String>) -> EmulatorError {
+        EmulatorError::InvalidScript {
+            line: self.line(),
+            reason: reason.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.idx).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.idx).map(|(_, token)| token.clone());
+        self.idx += 1;
+        token
+    }
+
+    fn word(&mut self, what: &str) -> Result<String, EmulatorError> {
+        match self.next() {
+            Some(Token::Word(word)) => Ok(word),
+            _ => {
+                self.idx -= 1;
+                Err(self.error(format!("expected {what}")))
+            }
+        }
+    }
+
+    fn variable(&mut self) -> Result<Variable, EmulatorError> {
+        let name = self.word("a variable")?;
+        parse_variable(&name).ok_or_else(|| self.error(format!("unknown variable `{name}`")))
+    }
+
+    fn value(&mut self) -> Result<u16, EmulatorError> {
+        let text = self.word("a value")?;
+        parse_value(&text).ok_or_else(|| self.error(format!("invalid value `{text}`")))
+    }
+
+    fn block(&mut self) -> Result<Vec<Command>, EmulatorError> {
+        if self.next() != Some(Token::Open) {
+            self.idx -= 1;
+            return Err(self.error("expected `{`"));
+        }
+        let commands = self.commands()?;
+        if self.next() != Some(Token::Close) {
+            return Err(self.error("expected `}`"));
+        }
+        Ok(commands)
+    }
+
+    fn command(&mut self) -> Result<Command, EmulatorError> {
+        let name = self.word("a command")?;
+        let command = match name.as_str() {
+            "load" => match self.peek() {
+                Some(Token::Word(_)) => Command::Load(Some(self.word("a file")?)),
+                _ => Command::Load(None),
+            },
+            "output-file" => Command::OutputFile(self.word("a file")?),
+            "compare-to" => Command::CompareTo(self.word("a file")?),
+            "output-list" => {
+                let mut columns = vec![];
+                while let Some(Token::Word(text)) = self.peek() {
+                    let column = parse_column(text)
+                        .ok_or_else(|| self.error(format!("invalid output column `{text}`")))?;
+                    columns.push(column);
+                    self.idx += 1;
+                }
+                Command::OutputList(columns)
+            }
+            "set" => {
+                let (variable, value) = (self.variable()?, self.value()?);
+                if variable == Variable::Pc && value as usize >= ROM_SIZE {
+                    let reason = format!("PC {} is past the end of ROM", value as i16);
+                    return Err(self.error(reason));
+                }
+                Command::Set(variable, value)
+            }
+            "tick" => Command::Tick,
+            "tock" => Command::Tock,
+            "ticktock" => Command::TickTock,
+            "output" => Command::Output,
+            "echo" => match self.next() {
+                Some(Token::Text(text) | Token::Word(text)) => Command::Echo(text),
+                _ => return Err(self.error("expected text after `echo`")),
+            },
+            "clear-echo" => Command::ClearEcho,
+            "repeat" => {
+                let count = match self.peek() {
+                    Some(Token::Word(count)) => {
+                        let count = count
+                            .parse()
+                            .map_err(|_| self.error(format!("invalid repeat count `{count}`")))?;
+                        self.idx += 1;
+                        Some(count)
+                    }
+                    _ => None,
+                };
+                // a block is a command of its own, no terminator follows it
+                return Ok(Command::Repeat(count, self.block()?));
+            }
+            "while" => {
+                let variable = self.variable()?;
+                let op = self.word("a comparison")?;
+                let op = parse_comparison(&op)
+                    .ok_or_else(|| self.error(format!("invalid comparison `{op}`")))?;
+                let value = self.value()?;
+                return Ok(Command::While(variable, op, value, self.block()?));
+            }
+            _ => return Err(self.error(format!("unsupported command `{name}`"))),
+        };
+        match self.next() {
+            Some(Token::End) => Ok(command),
+            _ => {
+                self.idx -= 1;
+                Err(self.error(format!("expected `,` or `;` after `{name}`")))
+            }
+        }
+    }
+
+    fn commands(&mut self) -> Result<Vec<Command>, EmulatorError> {
+        let mut commands = vec![];
+        while !matches!(self.peek(), None | Some(Token::Close)) {
+            commands.push(self.command()?);
+        }
+        Ok(commands)
+    }
+}
+
+// first output line that does not match the compare file, 1-based
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub line: usize,
+    pub expected: String,
+    pub actual: String,
+}
+
+// result of running a test script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestOutcome {
+    pub output_file: Option<PathBuf>,
+    pub output: String,
+    // None when the script has no compare-to
+    pub comparison: Option<Result<(), Mismatch>>,
+    pub echo: Vec<String>,
+}
+
+struct Runner {
+    cpu: Cpu,
+    dir: PathBuf,
+    script: PathBuf,
+    // a `tick` ran without its `tock`
+    half_cycle: bool,
+    columns: Vec<Column>,
+    outcome: TestOutcome,
+    compare_to: Option<PathBuf>,
+}
+
+impl Runner {
+    fn get(&self, variable: Variable) -> u16 {
+        match variable {
+            Variable::A => self.cpu.a,
+            Variable::D => self.cpu.d,
+            Variable::Pc => self.cpu.pc,
+            Variable::Ram(address) => self.cpu.ram[address as usize],
+            Variable::Rom(address) => self.cpu.rom[address as usize],
+            Variable::Time => self.cpu.cycles as u16,
+        }
+    }
+
+    fn set(&mut self, variable: Variable, value: u16) {
+        match variable {
+            Variable::A => self.cpu.a = value,
+            Variable::D => self.cpu.d = value,
+            Variable::Pc => self.cpu.pc = value,
+            Variable::Ram(address) => self.cpu.ram[address as usize] = value,
+            Variable::Rom(address) => self.cpu.rom[address as usize] = value,
+            Variable::Time => self.cpu.cycles = value as u64,
+        }
+    }
+
+    fn format(&self, column: &Column) -> String {
+        let value = self.get(column.variable);
+        let text = match column.format {
+            'X' => format!("{value:04X}"),
+            'B' => format!("{value:016b}"),
+            'S' if column.variable == Variable::Time => {
+                let half = if self.half_cycle { "+" } else { "" };
+                format!("{}{half}", self.cpu.cycles)
+            }
+            _ => (value as i16).to_string(),
+        };
+        // wide values keep their low-order characters
+        let text = &text[text.len().saturating_sub(column.len)..];
+        let text = if column.format == 'S' {
+            format!("{text:<len$}", len = column.len)
+        } else {
+            format!("{text:>len$}", len = column.len)
+        };
+        format!(
+            "{}{text}{}|",
+            " ".repeat(column.left),
+            " ".repeat(column.right)
+        )
+    }
+
+    fn header(&self) -> String {
+        let mut line = String::from("|");
+        for column in &self.columns {
+            let width = column.left + column.len + column.right;
+            let name = &column.name[..column.name.len().min(width)];
+            let left = (width - name.len()) / 2;
+            let right = width - name.len() - left;
+            line += &format!("{}{name}{}|", " ".repeat(left), " ".repeat(right));
+        }
+        line
+    }
+
+    fn tick(&mut self) -> Result<(), EmulatorError> {
+        self.cpu.step()?;
+        self.half_cycle = true;
+        Ok(())
+    }
+
+    fn execute(&mut self, commands: &[Command]) -> Result<(), EmulatorError> {
+        for command in commands {
+            match command {
+                Command::Load(file) => {
+                    let path = match file {
+                        Some(file) => self.dir.join(file),
+                        None => self.script.with_extension("hack"),
+                    };
+                    let program = load_program(&path)?;
+                    self.cpu.load(&program.words)?;
+                }
+                Command::OutputFile(file) => self.outcome.output_file = Some(self.dir.join(file)),
+                Command::CompareTo(file) => self.compare_to = Some(self.dir.join(file)),
+                Command::OutputList(columns) => {
+                    self.columns = columns.clone();
+                    let header = self.header();
+                    self.outcome.output += &format!("{header}\n");
+                }
+                Command::Set(variable, value) => self.set(*variable, *value),
+                Command::Tick => self.tick()?,
+                Command::Tock => self.half_cycle = false,
+                Command::TickTock => {
+                    self.tick()?;
+                    self.half_cycle = false;
+                }
+                Command::Output => {
+                    let mut line = String::from("|");
+                    for column in &self.columns {
+                        line += &self.format(column);
+                    }
+                    self.outcome.output += &format!("{line}\n");
+                }
+                Command::Echo(text) => self.outcome.echo.push(text.clone()),
+                Command::ClearEcho => {}
+                Command::Repeat(Some(count), body) => {
+                    for _ in 0..*count {
+                        self.execute(body)?;
+                    }
+                }
+                Command::Repeat(None, body) => {
+                    for _ in 0..MAX_REPEAT {
+                        if self.cpu.is_halted() {
+                            break;
+                        }
+                        self.execute(body)?;
+                    }
+                }
+                Command::While(variable, op, value, body) => {
+                    let holds = |runner: &Runner| {
+                        let (lhs, rhs) = (runner.get(*variable) as i16, *value as i16);
+                        match op {
+                            Comparison::Eq => lhs == rhs,
+                            Comparison::Ne => lhs != rhs,
+                            Comparison::Lt => lhs < rhs,
+                            Comparison::Gt => lhs > rhs,
+                            Comparison::Le => lhs <= rhs,
+                            Comparison::Ge => lhs >= rhs,
+                        }
+                    };
+                    while holds(self) {
+                        self.execute(body)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// `*` in a compare file line matches any character
+fn lines_match(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .chars()
+            .zip(actual.chars())
+            .all(|(e, a)| e == '*' || e == a)
+}
+
+fn compare(expected: &str, actual: &str) -> Result<(), Mismatch> {
+    let mut expected_lines = expected.lines().map(str::trim_end);
+    for (idx, actual) in actual.lines().enumerate() {
+        let expected = expected_lines.next().unwrap_or("");
+        if !lines_match(expected, actual) {
+            return Err(Mismatch {
+                line: idx + 1,
+                expected: expected.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// run a CPU emulator `.tst` script, writing its `.out` file and comparing it
+// with its `.cmp` file when the script names them
+pub fn run_test(script: &Path) -> Result<TestOutcome, EmulatorError> {
+    let io_error = |path: &Path, e: std::io::Error| EmulatorError::Io {
+        path: path.display().to_string(),
+        reason: e.to_string(),
+    };
+    let source = std::fs::read_to_string(script).map_err(|e| io_error(script, e))?;
+    let mut parser = Parser {
+        tokens: tokenize(&source),
+        idx: 0,
+    };
+    let commands = parser.commands()?;
+    if parser.idx < parser.tokens.len() {
+        return Err(parser.error("unexpected `}`"));
+    }
+
+    let mut runner = Runner {
+        cpu: Cpu::default(),
+        dir: script.parent().map_or_else(PathBuf::new, Path::to_path_buf),
+        script: script.to_path_buf(),
+        half_cycle: false,
+        columns: vec![],
+        outcome: TestOutcome {
+            output_file: None,
+            output: String::new(),
+            comparison: None,
+            echo: vec![],
+        },
+        compare_to: None,
+    };
+    runner.execute(&commands)?;
+
+    let mut outcome = runner.outcome;
+    if let Some(path) = &outcome.output_file {
+        std::fs::write(path, &outcome.output).map_err(|e| io_error(path, e))?;
+    }
+    if let Some(path) = &runner.compare_to {
+        let expected = std::fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+        outcome.comparison = Some(compare(&expected, &outcome.output));
+    }
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the course test scripts kept in examples/
+    #[test]
+    fn example_scripts_match_their_compare_files() {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples");
+        for script in ["Mult.tst", "FillAutomatic.tst"] {
+            let outcome = run_test(&examples.join(script)).unwrap();
+            assert_eq!(outcome.comparison, Some(Ok(())), "{script}");
+        }
+    }
+}
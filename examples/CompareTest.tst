@@ -0,0 +1,12 @@
+// Regression test for VM comparisons at the 16-bit boundaries.
+// Translate CompareTest.vm into CompareTest.asm in this directory first.
+
+load CompareTest.asm,
+output-file CompareTest.out,
+compare-to CompareTest.cmp,
+output-list RAM[5]%D1.6.1 RAM[6]%D1.6.1 RAM[7]%D1.6.1 RAM[8]%D1.6.1 RAM[9]%D1.6.1 RAM[10]%D1.6.1 RAM[11]%D1.6.1 RAM[12]%D1.6.1;
+
+repeat 2000 {
+  ticktock;
+}
+output;
@@ -0,0 +1,71 @@
+// Tests Mult.asm on the CPU emulator: RAM[2] = RAM[0] * RAM[1]
+
+load Mult.asm,
+output-file Mult.out,
+compare-to Mult.cmp,
+output-list RAM[0]%D2.6.2 RAM[1]%D2.6.2 RAM[2]%D2.6.2;
+
+set RAM[0] 0,   // set test arguments
+set RAM[1] 0,
+set RAM[2] -1;  // test that the program initialises the product to 0
+repeat 20 {
+  ticktock;
+}
+set RAM[0] 0,   // restore arguments in case the program used them as loop counters
+set RAM[1] 0;
+output;
+
+set PC 0,
+set RAM[0] 1,
+set RAM[1] 0,
+set RAM[2] -1;
+repeat 50 {
+  ticktock;
+}
+set RAM[0] 1,
+set RAM[1] 0;
+output;
+
+set PC 0,
+set RAM[0] 0,
+set RAM[1] 2,
+set RAM[2] -1;
+repeat 80 {
+  ticktock;
+}
+set RAM[0] 0,
+set RAM[1] 2;
+output;
+
+set PC 0,
+set RAM[0] 3,
+set RAM[1] 1,
+set RAM[2] -1;
+repeat 120 {
+  ticktock;
+}
+set RAM[0] 3,
+set RAM[1] 1;
+output;
+
+set PC 0,
+set RAM[0] 2,
+set RAM[1] 4,
+set RAM[2] -1;
+repeat 150 {
+  ticktock;
+}
+set RAM[0] 2,
+set RAM[1] 4;
+output;
+
+set PC 0,
+set RAM[0] 6,
+set RAM[1] 7,
+set RAM[2] -1;
+repeat 210 {
+  ticktock;
+}
+set RAM[0] 6,
+set RAM[1] 7;
+output;